@@ -0,0 +1,78 @@
+//! Compares a dense layer evaluated as one `matmul` against the scalar
+//! `Layer::eval` path, which builds two graph nodes per multiply-add.
+//!
+//! Run with `cargo bench --bench matmul`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use milligrad::fundamental::tensor::kernel::gemm;
+use milligrad::fundamental::tensor::{backward, matmul, Tensor};
+use milligrad::fundamental::unit::new_unit;
+use milligrad::nn::activation::Activation;
+use milligrad::nn::mlp::Layer;
+
+fn time<F: FnMut()>(name: &str, iters: u32, mut f: F) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    let per_iter = start.elapsed() / iters;
+    println!("{:<40} {:>12.3?} / iter", name, per_iter);
+    per_iter
+}
+
+fn values(len: usize) -> Vec<f32> {
+    (0..len).map(|i| ((i % 17) as f32 - 8.0) * 0.1).collect()
+}
+
+fn main() {
+    for &(batch, input, output) in &[(8, 16, 16), (32, 64, 64), (64, 128, 128)] {
+        println!("batch {} x {} -> {}", batch, input, output);
+
+        let layer = Layer::new(input as u32, output as u32, Activation::Identity);
+        let xs = values(batch * input);
+        let scalar = time("  Layer::eval (scalar units)", 5, || {
+            for row in xs.chunks(input) {
+                let x = row.iter().map(|&v| new_unit(v)).collect::<Vec<_>>();
+                black_box(layer.eval(&x));
+            }
+        });
+
+        let w = Tensor::new(values(input * output), &[input, output]);
+        let x = Tensor::new(xs.clone(), &[batch, input]);
+        let tensor = time("  matmul forward", 50, || {
+            black_box(matmul(&x, &w));
+        });
+        time("  matmul forward + backward", 50, || {
+            let y = matmul(&x, &w);
+            backward(&y);
+        });
+
+        let wv = values(input * output);
+        time("  gemm kernel only", 200, || {
+            let mut c = vec![0.0; batch * output];
+            gemm(batch, input, output, &xs, &wv, &mut c);
+            black_box(c);
+        });
+
+        println!(
+            "  speedup over scalar path: {:.1}x",
+            scalar.as_secs_f64() / tensor.as_secs_f64()
+        );
+    }
+
+    let n = 512;
+    let a = values(n * n);
+    let b = values(n * n);
+    let elapsed = time("gemm 512 x 512 x 512", 5, || {
+        let mut c = vec![0.0; n * n];
+        gemm(n, n, n, &a, &b, &mut c);
+        black_box(c);
+    });
+    println!(
+        "  {:.2} GFLOP/s",
+        2.0 * (n * n * n) as f64 / elapsed.as_secs_f64() / 1e9
+    );
+}
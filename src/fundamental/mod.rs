@@ -6,6 +6,7 @@ use std::rc::{Rc};
 
 use op::{add, div, mul};
 pub mod op;
+pub mod tensor;
 pub mod unit;
 
 use unit::_Unit;
@@ -23,6 +24,18 @@ pub enum Operation {
     Tanh(Unit),
     ReLU(Unit),
     Pow(Unit, f32),
+    Dot(Vec<Unit>, Vec<Unit>),
+    Exp(Unit),
+    Log(Unit),
+    Abs(Unit),
+    /// output `i` of a softmax over the inputs, with every output's probability
+    Softmax(Vec<Unit>, usize, Rc<Vec<f32>>),
+    LogSoftmax(Vec<Unit>, usize, Rc<Vec<f32>>),
+    LeakyReLU(Unit, f32),
+    Sigmoid(Unit),
+    GELU(Unit),
+    SiLU(Unit),
+    ELU(Unit, f32),
 }
 
 impl fmt::Debug for Operation {
@@ -35,6 +48,19 @@ impl fmt::Debug for Operation {
             Operation::Tanh(ref x) => write!(f, "Tanh {:?}", x.borrow().data),
             Operation::ReLU(ref x) => write!(f, "ReLU {:?}", x.borrow().data),
             Operation::Pow(ref x, ref r) => write!(f, "Pow {:?} {:?}", x.borrow().data, r),
+            Operation::Dot(ref w, _) => write!(f, "Dot {:?}", w.len()),
+            Operation::Exp(ref x) => write!(f, "Exp {:?}", x.borrow().data),
+            Operation::Log(ref x) => write!(f, "Log {:?}", x.borrow().data),
+            Operation::Abs(ref x) => write!(f, "Abs {:?}", x.borrow().data),
+            Operation::Softmax(ref xs, i, _) => write!(f, "Softmax {} of {}", i, xs.len()),
+            Operation::LogSoftmax(ref xs, i, _) => write!(f, "LogSoftmax {} of {}", i, xs.len()),
+            Operation::LeakyReLU(ref x, ref a) => {
+                write!(f, "LeakyReLU {:?} {:?}", x.borrow().data, a)
+            }
+            Operation::Sigmoid(ref x) => write!(f, "Sigmoid {:?}", x.borrow().data),
+            Operation::GELU(ref x) => write!(f, "GELU {:?}", x.borrow().data),
+            Operation::SiLU(ref x) => write!(f, "SiLU {:?}", x.borrow().data),
+            Operation::ELU(ref x, ref a) => write!(f, "ELU {:?} {:?}", x.borrow().data, a),
         }
     }
 }
@@ -49,6 +75,17 @@ impl fmt::Display for Operation {
             Operation::Tanh(_) => write!(f, "Tanh"),
             Operation::ReLU(_) => write!(f, "ReLU"),
             Operation::Pow(_, _) => write!(f, "Pow"),
+            Operation::Dot(_, _) => write!(f, "Dot"),
+            Operation::Exp(_) => write!(f, "Exp"),
+            Operation::Log(_) => write!(f, "Log"),
+            Operation::Abs(_) => write!(f, "Abs"),
+            Operation::Softmax(_, _, _) => write!(f, "Softmax"),
+            Operation::LogSoftmax(_, _, _) => write!(f, "LogSoftmax"),
+            Operation::LeakyReLU(_, _) => write!(f, "LeakyReLU"),
+            Operation::Sigmoid(_) => write!(f, "Sigmoid"),
+            Operation::GELU(_) => write!(f, "GELU"),
+            Operation::SiLU(_) => write!(f, "SiLU"),
+            Operation::ELU(_, _) => write!(f, "ELU"),
         }
     }
 }
@@ -77,6 +114,16 @@ impl Unit {
         self.borrow_mut().grad = 0.0;
     }
 
+    pub fn requires_grad(&self) -> bool {
+        self.borrow().requires_grad
+    }
+
+    /// Marks the unit as trainable or frozen. A frozen unit keeps a zero
+    /// gradient, and `backward` does not visit the graph behind it.
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        self.borrow_mut().requires_grad = requires_grad;
+    }
+
     pub fn adjust(&self, learning_rate: f32) {
         let mut u = self.borrow_mut();
         u.data += learning_rate * u.grad;
@@ -110,7 +157,7 @@ impl Neg for Unit {
     }
 }
 
-impl<'a> Neg for &'a Unit {
+impl Neg for &Unit {
     type Output = Unit;
 
     fn neg(self) -> Self::Output {
@@ -126,10 +173,10 @@ impl Add<Unit> for Unit {
     }
 }
 
-impl<'a, 'b> Add<&'b Unit> for &'a Unit {
+impl Add<&Unit> for &Unit {
     type Output = Unit;
 
-    fn add(self, other: &'b Unit) -> Self::Output {
+    fn add(self, other: &Unit) -> Self::Output {
         add(self, other)
     }
 }
@@ -142,10 +189,10 @@ impl Sub<Unit> for Unit {
     }
 }
 
-impl<'a, 'b> Sub<&'b Unit> for &'a Unit {
+impl Sub<&Unit> for &Unit {
     type Output = Unit;
 
-    fn sub(self, other: &'b Unit) -> Self::Output {
+    fn sub(self, other: &Unit) -> Self::Output {
         add(self, &-other)
     }
 }
@@ -158,10 +205,10 @@ impl Mul<Unit> for Unit {
     }
 }
 
-impl<'a, 'b> Mul<&'b Unit> for &'a Unit {
+impl Mul<&Unit> for &Unit {
     type Output = Unit;
 
-    fn mul(self, other: &'b Unit) -> Self::Output {
+    fn mul(self, other: &Unit) -> Self::Output {
         mul(self, other)
     }
 }
@@ -174,10 +221,10 @@ impl Div<Unit> for Unit {
     }
 }
 
-impl<'a, 'b> Div<&'b Unit> for &'a Unit {
+impl Div<&Unit> for &Unit {
     type Output = Unit;
 
-    fn div(self, other: &'b Unit) -> Self::Output {
+    fn div(self, other: &Unit) -> Self::Output {
         div(self, other)
     }
 }
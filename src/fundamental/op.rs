@@ -48,6 +48,124 @@ pub fn relu(x: &Unit) -> Unit {
     Unit::new(_Unit::new(result, op, vec![x.clone()]))
 }
 
+/// `sum(w[i] * x[i])` as a single node, instead of a chain of `mul` and `add`.
+pub fn dot(w: &[Unit], x: &[Unit]) -> Unit {
+    assert_eq!(
+        w.len(),
+        x.len(),
+        "dot product of {} weights with {} inputs",
+        w.len(),
+        x.len()
+    );
+    let result = w
+        .iter()
+        .zip(x.iter())
+        .map(|(a, b)| a.borrow().data * b.borrow().data)
+        .sum();
+    let mut children = w.to_vec();
+    children.extend_from_slice(x);
+    let op = Some(Operation::Dot(w.to_vec(), x.to_vec()));
+    Unit::new(_Unit::new(result, op, children))
+}
+
+pub fn exp(x: &Unit) -> Unit {
+    let result = x.borrow().data.exp();
+    let op = Some(Operation::Exp(x.clone()));
+    Unit::new(_Unit::new(result, op, vec![x.clone()]))
+}
+
+/// Natural logarithm.
+pub fn log(x: &Unit) -> Unit {
+    let result = x.borrow().data.ln();
+    let op = Some(Operation::Log(x.clone()));
+    Unit::new(_Unit::new(result, op, vec![x.clone()]))
+}
+
+pub fn abs(x: &Unit) -> Unit {
+    let result = x.borrow().data.abs();
+    let op = Some(Operation::Abs(x.clone()));
+    Unit::new(_Unit::new(result, op, vec![x.clone()]))
+}
+
+/// Probabilities and log-probabilities of `xs`, shifted by the largest value
+/// so `exp` cannot overflow.
+fn softmax_values(xs: &[Unit]) -> (Vec<f32>, Vec<f32>) {
+    let data = xs.iter().map(|x| x.borrow().data).collect::<Vec<_>>();
+    let m = data.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let shifted = data.iter().map(|v| (v - m).exp()).collect::<Vec<_>>();
+    let sum = shifted.iter().sum::<f32>();
+    let probs = shifted.iter().map(|e| e / sum).collect();
+    let log_probs = data.iter().map(|v| (v - m) - sum.ln()).collect();
+    (probs, log_probs)
+}
+
+/// Softmax over `xs`. Each output is a single node whose backward uses the
+/// probabilities saved here, instead of a graph of `exp`, `add` and `div`.
+pub fn softmax(xs: &[Unit]) -> Vec<Unit> {
+    let (probs, _) = softmax_values(xs);
+    let probs = Rc::new(probs);
+    (0..xs.len())
+        .map(|i| {
+            let op = Some(Operation::Softmax(xs.to_vec(), i, probs.clone()));
+            Unit::new(_Unit::new(probs[i], op, xs.to_vec()))
+        })
+        .collect()
+}
+
+/// `x_i - logsumexp(xs)`, computed without forming the probabilities first,
+/// so very negative log-probabilities stay finite.
+pub fn log_softmax(xs: &[Unit]) -> Vec<Unit> {
+    let (probs, log_probs) = softmax_values(xs);
+    let probs = Rc::new(probs);
+    (0..xs.len())
+        .map(|i| {
+            let op = Some(Operation::LogSoftmax(xs.to_vec(), i, probs.clone()));
+            Unit::new(_Unit::new(log_probs[i], op, xs.to_vec()))
+        })
+        .collect()
+}
+
+pub fn leaky_relu(x: &Unit, alpha: f32) -> Unit {
+    let v = x.borrow().data;
+    let result = if v > 0.0 { v } else { alpha * v };
+    let op = Some(Operation::LeakyReLU(x.clone(), alpha));
+    Unit::new(_Unit::new(result, op, vec![x.clone()]))
+}
+
+pub fn sigmoid(x: &Unit) -> Unit {
+    let result = unit::sigmoid(x.borrow().data);
+    let op = Some(Operation::Sigmoid(x.clone()));
+    Unit::new(_Unit::new(result, op, vec![x.clone()]))
+}
+
+pub fn gelu(x: &Unit) -> Unit {
+    let result = unit::gelu(x.borrow().data);
+    let op = Some(Operation::GELU(x.clone()));
+    Unit::new(_Unit::new(result, op, vec![x.clone()]))
+}
+
+pub fn silu(x: &Unit) -> Unit {
+    let v = x.borrow().data;
+    let result = v * unit::sigmoid(v);
+    let op = Some(Operation::SiLU(x.clone()));
+    Unit::new(_Unit::new(result, op, vec![x.clone()]))
+}
+
+pub fn elu(x: &Unit, alpha: f32) -> Unit {
+    let v = x.borrow().data;
+    let result = if v > 0.0 { v } else { alpha * (v.exp() - 1.0) };
+    let op = Some(Operation::ELU(x.clone(), alpha));
+    Unit::new(_Unit::new(result, op, vec![x.clone()]))
+}
+
+/// A leaf with the value of `x` that needs no gradient. Gradients stop here
+/// instead of flowing into the graph that produced `x`.
+pub fn detach(x: &Unit) -> Unit {
+    let u = new_unit(x.borrow().data);
+    u.set_requires_grad(false);
+    u
+}
+
 pub fn backward(u: &Unit) {
     u.borrow_mut().grad = 1.0;
     let topo_n = topological_sort_circle(u);
@@ -65,6 +183,7 @@ pub fn backward(u: &Unit) {
 }
 
 use std::collections::{HashMap, HashSet, VecDeque};
+#[allow(dead_code)]
 fn topological_sort(graph: &HashMap<usize, Vec<usize>>) -> Option<Vec<usize>> {
     let mut in_degrees = HashMap::new();
     let mut queue = VecDeque::new();
@@ -134,7 +253,13 @@ fn detect_cycle(
     stack.insert(id); // Mark node as being on the stack
     visited.insert(id);
 
-    for child in &node.borrow().children {
+    // subgraphs that need no gradient are left out of the backward pass
+    for child in node
+        .borrow()
+        .children
+        .iter()
+        .filter(|c| c.borrow().requires_grad)
+    {
         if detect_cycle(child, visited, stack, result) {
             return true; // Propagate cycle detection
         }
@@ -145,6 +270,7 @@ fn detect_cycle(
     false
 }
 
+#[allow(dead_code)]
 fn rev_topological_sort_dfs(u: &Unit) -> Option<Vec<Unit>> {
     let mut visited: HashSet<usize> = HashSet::new();
     let mut sorted = Vec::new();
@@ -193,11 +319,39 @@ mod tests {
         assert_eq!(b.borrow().grad, 1.0);
     }
 
+    #[test]
+    fn test_square_by_self_multiplication() {
+        let x = new_unit(3.0);
+        let y = mul(&x, &x);
+        backward(&y);
+        assert_eq!(y.data(), 9.0);
+        assert_eq!(x.grad(), 6.0);
+    }
+
+    #[test]
+    fn test_frozen_subgraph_is_skipped() {
+        let x = new_unit(0.5);
+        x.set_requires_grad(false);
+        let w = new_unit(2.0);
+        let h = tanh(&x);
+        assert!(!h.requires_grad());
+        let y = mul(&h, &w);
+        assert!(y.requires_grad());
+
+        // `h` and `x` are never visited
+        assert_eq!(topological_sort_circle(&y).unwrap().len(), 2);
+        backward(&y);
+        assert_eq!(w.grad(), 0.5f32.tanh());
+        assert_eq!(h.grad(), 0.0);
+        assert_eq!(x.grad(), 0.0);
+        assert!(!detach(&y).requires_grad());
+    }
+
     use std::collections::{HashMap};
 
     #[test]
     fn test_topo() {
-        let graph = vec![(2, vec![3, 0, 1]), (3, vec![1]), (0, vec![1]), (1, vec![4])]
+        let graph = [(2, vec![3, 0, 1]), (3, vec![1]), (0, vec![1]), (1, vec![4])]
             .iter()
             .cloned()
             .collect::<HashMap<_, _>>();
@@ -239,7 +393,46 @@ mod tests {
         let f = add(&d, &e); // 2.99505475
         let g = add(&f, &f); // 5.9901095
         let sorted = rev_topological_sort_dfs(&g);
-        assert!(sorted.is_none());
+        // reusing `f` makes a diamond, not a cycle: every node is visited once
+        assert_eq!(sorted.map(|s| s.len()), Some(7));
+        assert_eq!(topological_sort_circle(&g).map(|s| s.len()), Some(7));
+    }
+
+    #[test]
+    fn test_softmax_is_stable_and_fused() {
+        let xs = vec![new_unit(1000.0), new_unit(999.0), new_unit(-1000.0)];
+        let p = softmax(&xs);
+        let total = p.iter().map(|u| u.data()).sum::<f32>();
+        assert!((total - 1.0).abs() < 1e-6);
+        assert!(p[2].data() == 0.0 && p[0].data() > p[1].data());
+        // one node per output, wired straight to the inputs
+        assert_eq!(p[0].borrow().children.len(), 3);
+
+        let lp = log_softmax(&xs);
+        assert!((lp[2].data() + 2000.0 + (1.0 + (-1.0f32).exp()).ln()).abs() < 1e-2);
+        assert!(lp.iter().all(|u| u.data().is_finite()));
+    }
+
+    #[test]
+    fn test_softmax_gradients_match_finite_differences() {
+        let values = [0.3f32, -1.2, 2.0];
+        let eps = 1e-2;
+        for (f, out) in [(softmax as fn(&[Unit]) -> Vec<Unit>, 0), (log_softmax, 2)] {
+            let xs = values.iter().map(|&v| new_unit(v)).collect::<Vec<_>>();
+            backward(&f(&xs)[out]);
+            for (j, x) in xs.iter().enumerate() {
+                let shifted = |d: f32| {
+                    let ys = values
+                        .iter()
+                        .enumerate()
+                        .map(|(k, &v)| new_unit(if k == j { v + d } else { v }))
+                        .collect::<Vec<_>>();
+                    f(&ys)[out].data()
+                };
+                let numeric = (shifted(eps) - shifted(-eps)) / (2.0 * eps);
+                assert!((x.grad() - numeric).abs() < 1e-3);
+            }
+        }
     }
 
     #[test]
@@ -0,0 +1,381 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{Tensor, TensorOp};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EinsumError {
+    /// a subscript that is not an ASCII letter
+    InvalidCharacter(char),
+    /// more than one `->`, or an empty operand list
+    Malformed(String),
+    OperandCount {
+        expected: usize,
+        got: usize,
+    },
+    RankMismatch {
+        operand: usize,
+        subscripts: String,
+        shape: Vec<usize>,
+    },
+    SizeMismatch {
+        label: char,
+        first: usize,
+        second: usize,
+    },
+    UnknownOutputLabel(char),
+    RepeatedOutputLabel(char),
+}
+
+impl fmt::Display for EinsumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EinsumError::InvalidCharacter(c) => {
+                write!(
+                    f,
+                    "invalid subscript {:?}, only ASCII letters are allowed",
+                    c
+                )
+            }
+            EinsumError::Malformed(spec) => write!(f, "malformed einsum spec {:?}", spec),
+            EinsumError::OperandCount { expected, got } => write!(
+                f,
+                "spec names {} operands but {} tensors were given",
+                expected, got
+            ),
+            EinsumError::RankMismatch {
+                operand,
+                subscripts,
+                shape,
+            } => write!(
+                f,
+                "operand {} has shape {:?} but subscripts {:?}",
+                operand, shape, subscripts
+            ),
+            EinsumError::SizeMismatch {
+                label,
+                first,
+                second,
+            } => write!(
+                f,
+                "subscript {:?} is used for axes of size {} and {}",
+                label, first, second
+            ),
+            EinsumError::UnknownOutputLabel(c) => {
+                write!(f, "output subscript {:?} does not appear in any input", c)
+            }
+            EinsumError::RepeatedOutputLabel(c) => {
+                write!(f, "output subscript {:?} appears more than once", c)
+            }
+        }
+    }
+}
+
+impl Error for EinsumError {}
+
+/// A parsed einsum spec with every subscript letter replaced by a label number,
+/// plus the size each label was bound to.
+#[derive(Clone, Debug)]
+pub struct Subscripts {
+    pub(crate) inputs: Vec<Vec<usize>>,
+    pub(crate) output: Vec<usize>,
+    pub(crate) sizes: Vec<usize>,
+}
+
+fn parse(spec: &str, shapes: &[Vec<usize>]) -> Result<Subscripts, EinsumError> {
+    let compact = spec
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let mut sides = compact.split("->");
+    let lhs = sides.next().unwrap_or("");
+    let rhs = sides.next();
+    if sides.next().is_some() || lhs.is_empty() {
+        return Err(EinsumError::Malformed(spec.to_string()));
+    }
+
+    let terms = lhs.split(',').collect::<Vec<_>>();
+    if terms.len() != shapes.len() {
+        return Err(EinsumError::OperandCount {
+            expected: terms.len(),
+            got: shapes.len(),
+        });
+    }
+
+    let mut letters: Vec<char> = Vec::new();
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut inputs = Vec::with_capacity(terms.len());
+    for (k, (term, shape)) in terms.iter().zip(shapes.iter()).enumerate() {
+        if let Some(c) = term.chars().find(|c| !c.is_ascii_alphabetic()) {
+            return Err(EinsumError::InvalidCharacter(c));
+        }
+        if term.len() != shape.len() {
+            return Err(EinsumError::RankMismatch {
+                operand: k,
+                subscripts: term.to_string(),
+                shape: shape.clone(),
+            });
+        }
+        let mut labels = Vec::with_capacity(term.len());
+        for (c, &n) in term.chars().zip(shape.iter()) {
+            let label = match letters.iter().position(|&l| l == c) {
+                Some(l) if sizes[l] != n => {
+                    return Err(EinsumError::SizeMismatch {
+                        label: c,
+                        first: sizes[l],
+                        second: n,
+                    })
+                }
+                Some(l) => l,
+                None => {
+                    letters.push(c);
+                    sizes.push(n);
+                    letters.len() - 1
+                }
+            };
+            labels.push(label);
+        }
+        inputs.push(labels);
+    }
+
+    let output = match rhs {
+        Some(rhs) => {
+            let mut output = Vec::with_capacity(rhs.len());
+            for c in rhs.chars() {
+                if !c.is_ascii_alphabetic() {
+                    return Err(EinsumError::InvalidCharacter(c));
+                }
+                let label = letters
+                    .iter()
+                    .position(|&l| l == c)
+                    .ok_or(EinsumError::UnknownOutputLabel(c))?;
+                if output.contains(&label) {
+                    return Err(EinsumError::RepeatedOutputLabel(c));
+                }
+                output.push(label);
+            }
+            output
+        }
+        None => {
+            // implicit output: the letters used exactly once, in alphabetical order
+            let mut once = (0..letters.len())
+                .filter(|&l| inputs.iter().flatten().filter(|&&x| x == l).count() == 1)
+                .collect::<Vec<_>>();
+            once.sort_by_key(|&l| letters[l]);
+            once
+        }
+    };
+
+    Ok(Subscripts {
+        inputs,
+        output,
+        sizes,
+    })
+}
+
+/// Strides of a tensor laid out as `labels`, indexed by label. A label that
+/// repeats adds its strides up, which walks the diagonal.
+fn label_strides(labels: &[usize], sizes: &[usize]) -> Vec<usize> {
+    let mut strides = vec![0; sizes.len()];
+    let mut stride = 1;
+    for &l in labels.iter().rev() {
+        strides[l] += stride;
+        stride *= sizes[l];
+    }
+    strides
+}
+
+/// Sums the product of `operands` over every assignment of the labels,
+/// writing into a tensor laid out as `output`. Labels only in `output` are
+/// broadcast, and a repeated output label writes the diagonal.
+fn contract(operands: &[(&[usize], &[f32])], output: &[usize], sizes: &[usize]) -> Vec<f32> {
+    let out_len = output.iter().map(|&l| sizes[l]).product::<usize>();
+    let mut out = vec![0.0; out_len];
+    let total = sizes.iter().product::<usize>();
+    if total == 0 {
+        return out;
+    }
+
+    let in_strides = operands
+        .iter()
+        .map(|(labels, _)| label_strides(labels, sizes))
+        .collect::<Vec<_>>();
+    let out_strides = label_strides(output, sizes);
+
+    let mut coord = vec![0; sizes.len()];
+    let mut pos = vec![0; operands.len()];
+    let mut out_pos = 0;
+    for _ in 0..total {
+        let mut product = 1.0;
+        for ((_, values), &p) in operands.iter().zip(pos.iter()) {
+            product *= values[p];
+        }
+        out[out_pos] += product;
+
+        for l in (0..sizes.len()).rev() {
+            coord[l] += 1;
+            for (p, s) in pos.iter_mut().zip(in_strides.iter()) {
+                *p += s[l];
+            }
+            out_pos += out_strides[l];
+            if coord[l] < sizes[l] {
+                break;
+            }
+            for (p, s) in pos.iter_mut().zip(in_strides.iter()) {
+                *p -= s[l] * coord[l];
+            }
+            out_pos -= out_strides[l] * coord[l];
+            coord[l] = 0;
+        }
+    }
+    out
+}
+
+/// Einstein summation, e.g. `einsum("bij,bjk->bik", &[&a, &b])` for a
+/// batched matrix product. Without `->` the output keeps the subscripts that
+/// appear exactly once, sorted alphabetically.
+pub fn einsum(spec: &str, operands: &[&Tensor]) -> Result<Tensor, EinsumError> {
+    let shapes = operands.iter().map(|t| t.shape()).collect::<Vec<_>>();
+    let subscripts = parse(spec, &shapes)?;
+
+    let values = operands.iter().map(|t| t.contiguous()).collect::<Vec<_>>();
+    let inputs = subscripts
+        .inputs
+        .iter()
+        .zip(values.iter())
+        .map(|(labels, v)| (labels.as_slice(), v.as_slice()))
+        .collect::<Vec<_>>();
+    let out = contract(&inputs, &subscripts.output, &subscripts.sizes);
+
+    let shape = subscripts
+        .output
+        .iter()
+        .map(|&l| subscripts.sizes[l])
+        .collect();
+    let operands = operands.iter().map(|&t| t.clone()).collect::<Vec<_>>();
+    Ok(Tensor::from_op(
+        out,
+        shape,
+        TensorOp::Einsum(operands.clone(), subscripts),
+        operands,
+    ))
+}
+
+/// The gradient of operand `k` is itself an einsum: the output gradient and
+/// the other operands contracted onto the subscripts of operand `k`.
+pub(super) fn backward(grad: &[f32], operands: &[Tensor], subscripts: &Subscripts) {
+    let values = operands.iter().map(|t| t.contiguous()).collect::<Vec<_>>();
+    for (k, x) in operands.iter().enumerate() {
+        let mut inputs = subscripts
+            .inputs
+            .iter()
+            .zip(values.iter())
+            .enumerate()
+            .filter(|&(j, _)| j != k)
+            .map(|(_, (labels, v))| (labels.as_slice(), v.as_slice()))
+            .collect::<Vec<_>>();
+        inputs.push((subscripts.output.as_slice(), grad));
+        let dx = contract(&inputs, &subscripts.inputs[k], &subscripts.sizes);
+        x.accumulate_grad(&dx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::tensor::{backward, matmul};
+
+    fn arange(shape: &[usize]) -> Tensor {
+        let n = shape.iter().product::<usize>();
+        Tensor::new((0..n).map(|v| v as f32 * 0.5 - 1.0).collect(), shape)
+    }
+
+    #[test]
+    fn test_einsum_batched_matmul_matches_matmul() {
+        let a = arange(&[2, 3, 4]);
+        let b = arange(&[2, 4, 5]);
+        let e = einsum("bij,bjk->bik", &[&a, &b]).unwrap();
+        let m = matmul(&a, &b);
+        assert_eq!(e.shape(), m.shape());
+        assert_eq!(e.data(), m.data());
+
+        backward(&e);
+        let (ga, gb) = (a.grad(), b.grad());
+        a.zero_grad();
+        b.zero_grad();
+        backward(&m);
+        assert_eq!(ga, a.grad());
+        assert_eq!(gb, b.grad());
+    }
+
+    #[test]
+    fn test_einsum_implicit_output_and_trace() {
+        let a = arange(&[2, 3]);
+        // "ij,jk" keeps i and k
+        let b = arange(&[3, 2]);
+        assert_eq!(einsum("ij,jk", &[&a, &b]).unwrap().shape(), vec![2, 2]);
+        assert_eq!(
+            einsum("ij->ji", &[&a]).unwrap().data(),
+            vec![-1.0, 0.5, -0.5, 1.0, 0.0, 1.5]
+        );
+
+        let sq = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2]);
+        let trace = einsum("ii->", &[&sq]).unwrap();
+        assert_eq!(trace.item(), 5.0);
+        backward(&trace);
+        assert_eq!(sq.grad(), vec![1.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_einsum_broadcast_backward() {
+        // the gradient of a row sum broadcasts back over the summed axis
+        let x = arange(&[2, 3]);
+        let s = einsum("ij->i", &[&x]).unwrap();
+        assert_eq!(s.data(), vec![-1.5, 3.0]);
+        backward(&s);
+        assert_eq!(x.grad(), vec![1.0; 6]);
+    }
+
+    #[test]
+    fn test_einsum_errors() {
+        let a = arange(&[2, 3]);
+        let b = arange(&[4, 2]);
+        assert_eq!(
+            einsum("ij,jk->ik", &[&a, &b]).unwrap_err(),
+            EinsumError::SizeMismatch {
+                label: 'j',
+                first: 3,
+                second: 4
+            }
+        );
+        assert_eq!(
+            einsum("ij,jk->ik", &[&a]).unwrap_err(),
+            EinsumError::OperandCount {
+                expected: 2,
+                got: 1
+            }
+        );
+        assert!(matches!(
+            einsum("ijk->i", &[&a]),
+            Err(EinsumError::RankMismatch { operand: 0, .. })
+        ));
+        assert_eq!(
+            einsum("ij->iz", &[&a]).unwrap_err(),
+            EinsumError::UnknownOutputLabel('z')
+        );
+        assert_eq!(
+            einsum("ij->ii", &[&a]).unwrap_err(),
+            EinsumError::RepeatedOutputLabel('i')
+        );
+        assert_eq!(
+            einsum("i1->i", &[&a]).unwrap_err(),
+            EinsumError::InvalidCharacter('1')
+        );
+        assert!(matches!(
+            einsum("ij->i->j", &[&a]),
+            Err(EinsumError::Malformed(_))
+        ));
+        let msg = einsum("ij,jk->ik", &[&a, &b]).unwrap_err().to_string();
+        assert!(msg.contains("size 3 and 4"), "{}", msg);
+    }
+}
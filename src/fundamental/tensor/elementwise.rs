@@ -0,0 +1,152 @@
+use super::shape::{contiguous_strides, strided_indices};
+use super::{Tensor, TensorOp};
+
+/// Result shape of broadcasting `a` against `b`, aligning trailing axes.
+fn broadcast_shape(a: &[usize], b: &[usize]) -> Vec<usize> {
+    let ndim = a.len().max(b.len());
+    (0..ndim)
+        .map(|d| {
+            let x = if d + a.len() >= ndim {
+                a[d + a.len() - ndim]
+            } else {
+                1
+            };
+            let y = if d + b.len() >= ndim {
+                b[d + b.len() - ndim]
+            } else {
+                1
+            };
+            assert!(
+                x == y || x == 1 || y == 1,
+                "shapes {:?} and {:?} cannot be broadcast together",
+                a,
+                b
+            );
+            x.max(y)
+        })
+        .collect()
+}
+
+/// Position in an operand of shape `shape` for every element of `out_shape`.
+fn broadcast_indices(shape: &[usize], out_shape: &[usize]) -> Vec<usize> {
+    let strides = contiguous_strides(shape);
+    let lead = out_shape.len() - shape.len();
+    let walk = (0..out_shape.len())
+        .map(|d| {
+            if d < lead || shape[d - lead] == 1 {
+                0
+            } else {
+                strides[d - lead]
+            }
+        })
+        .collect::<Vec<_>>();
+    strided_indices(out_shape, &walk, 0)
+}
+
+fn binary(
+    a: &Tensor,
+    b: &Tensor,
+    op: fn(Tensor, Tensor) -> TensorOp,
+    f: fn(f32, f32) -> f32,
+) -> Tensor {
+    let (a_shape, b_shape) = (a.shape(), b.shape());
+    let shape = broadcast_shape(&a_shape, &b_shape);
+    let (av, bv) = (a.contiguous(), b.contiguous());
+    let out = broadcast_indices(&a_shape, &shape)
+        .into_iter()
+        .zip(broadcast_indices(&b_shape, &shape))
+        .map(|(i, j)| f(av[i], bv[j]))
+        .collect();
+    Tensor::from_op(
+        out,
+        shape,
+        op(a.clone(), b.clone()),
+        vec![a.clone(), b.clone()],
+    )
+}
+
+/// Element-wise `a + b` with NumPy-style broadcasting.
+pub fn add(a: &Tensor, b: &Tensor) -> Tensor {
+    binary(a, b, TensorOp::Add, |x, y| x + y)
+}
+
+pub fn sub(a: &Tensor, b: &Tensor) -> Tensor {
+    binary(a, b, TensorOp::Sub, |x, y| x - y)
+}
+
+pub fn mul(a: &Tensor, b: &Tensor) -> Tensor {
+    binary(a, b, TensorOp::Mul, |x, y| x * y)
+}
+
+pub fn div(a: &Tensor, b: &Tensor) -> Tensor {
+    binary(a, b, TensorOp::Div, |x, y| x / y)
+}
+
+/// Sends the gradient of a broadcast binary op back to both operands. A
+/// broadcast operand collects the sum over the axes it was repeated along.
+pub(super) fn backward(grad: &[f32], out_shape: &[usize], op: &TensorOp) {
+    let (a, b) = match op {
+        TensorOp::Add(a, b) | TensorOp::Sub(a, b) | TensorOp::Mul(a, b) | TensorOp::Div(a, b) => {
+            (a, b)
+        }
+        _ => unreachable!("not an element-wise op: {}", op),
+    };
+    let ai = broadcast_indices(&a.shape(), out_shape);
+    let bi = broadcast_indices(&b.shape(), out_shape);
+    let (av, bv) = (a.contiguous(), b.contiguous());
+    let mut da = vec![0.0; a.numel()];
+    let mut db = vec![0.0; b.numel()];
+    for ((&g, &i), &j) in grad.iter().zip(ai.iter()).zip(bi.iter()) {
+        let (x, y) = (av[i], bv[j]);
+        let (ga, gb) = match op {
+            TensorOp::Add(_, _) => (g, g),
+            TensorOp::Sub(_, _) => (g, -g),
+            TensorOp::Mul(_, _) => (g * y, g * x),
+            _ => (g / y, -g * x / (y * y)),
+        };
+        da[i] += ga;
+        db[j] += gb;
+    }
+    a.accumulate_grad(&da);
+    b.accumulate_grad(&db);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::tensor::{backward, sum};
+
+    #[test]
+    fn test_broadcast_add_mul() {
+        let x = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+        let row = Tensor::new(vec![10.0, 20.0, 30.0], &[3]);
+        let col = Tensor::new(vec![2.0, 3.0], &[2, 1]);
+
+        let y = mul(&add(&x, &row), &col);
+        assert_eq!(y.shape(), vec![2, 3]);
+        assert_eq!(y.data(), vec![22.0, 44.0, 66.0, 42.0, 75.0, 108.0]);
+
+        backward(&sum(&y, &[], false));
+        assert_eq!(x.grad(), vec![2.0, 2.0, 2.0, 3.0, 3.0, 3.0]);
+        assert_eq!(row.grad(), vec![5.0, 5.0, 5.0]);
+        assert_eq!(col.grad(), vec![66.0, 75.0]);
+    }
+
+    #[test]
+    fn test_sub_div_gradients() {
+        let a = Tensor::new(vec![6.0, 8.0], &[2]);
+        let b = Tensor::scalar(2.0);
+        let y = div(&sub(&a, &b), &b);
+        assert_eq!(y.data(), vec![2.0, 3.0]);
+        backward(&sum(&y, &[], false));
+        assert_eq!(a.grad(), vec![0.5, 0.5]);
+        // d/db (a - b) / b = -a / b^2, summed over both elements
+        assert_eq!(b.grad(), vec![-3.5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot be broadcast")]
+    fn test_incompatible_shapes() {
+        add(&Tensor::zeros(&[2, 3]), &Tensor::zeros(&[2]));
+    }
+}
@@ -0,0 +1,233 @@
+use super::shape::{contiguous_strides, strided_indices};
+use super::{Tensor, TensorOp};
+
+/// Flat position in a tensor of shape `x_shape` read by every element of an
+/// `index` of shape `index_shape` along `axis`, as `gather` defines it.
+fn gather_positions(
+    x_shape: &[usize],
+    axis: usize,
+    index: &[usize],
+    index_shape: &[usize],
+) -> Vec<usize> {
+    assert!(
+        axis < x_shape.len(),
+        "axis {} out of range for tensor of shape {:?}",
+        axis,
+        x_shape
+    );
+    assert_eq!(
+        index.len(),
+        index_shape.iter().product::<usize>(),
+        "index of shape {:?} needs {} entries, got {}",
+        index_shape,
+        index_shape.iter().product::<usize>(),
+        index.len()
+    );
+    let fits = index_shape.len() == x_shape.len()
+        && (0..x_shape.len()).all(|d| d == axis || index_shape[d] <= x_shape[d]);
+    assert!(
+        fits,
+        "index of shape {:?} does not fit tensor of shape {:?} along axis {}",
+        index_shape, x_shape, axis
+    );
+
+    let strides = contiguous_strides(x_shape);
+    let mut walk = strides.clone();
+    walk[axis] = 0;
+    strided_indices(index_shape, &walk, 0)
+        .into_iter()
+        .zip(index.iter())
+        .map(|(base, &i)| {
+            assert!(
+                i < x_shape[axis],
+                "index {} out of range for axis {} of shape {:?}",
+                i,
+                axis,
+                x_shape
+            );
+            base + i * strides[axis]
+        })
+        .collect()
+}
+
+/// `dst[positions[j]] += src[j]`, so repeated positions accumulate.
+pub(crate) fn scatter_add_values(dst: &mut [f32], positions: &[usize], src: &[f32]) {
+    for (&p, v) in positions.iter().zip(src.iter()) {
+        dst[p] += v;
+    }
+}
+
+fn gather_values(src: &[f32], positions: &[usize]) -> Vec<f32> {
+    positions.iter().map(|&p| src[p]).collect()
+}
+
+/// Picks `x[.., index[i, j, ..], ..]` along `axis` for every entry of `index`.
+/// The result has the shape of `index`.
+pub fn gather(x: &Tensor, axis: usize, index: &[usize], index_shape: &[usize]) -> Tensor {
+    let positions = gather_positions(&x.shape(), axis, index, index_shape);
+    let out = gather_values(&x.contiguous(), &positions);
+    Tensor::from_op(
+        out,
+        index_shape.to_vec(),
+        TensorOp::Gather(x.clone(), positions),
+        vec![x.clone()],
+    )
+}
+
+/// A copy of `x` with every element of `src` added at the position `gather`
+/// would have read it from. Duplicate indices accumulate.
+pub fn scatter_add(x: &Tensor, axis: usize, index: &[usize], src: &Tensor) -> Tensor {
+    let positions = gather_positions(&x.shape(), axis, index, &src.shape());
+    let mut out = x.data();
+    scatter_add_values(&mut out, &positions, &src.contiguous());
+    Tensor::from_op(
+        out,
+        x.shape(),
+        TensorOp::ScatterAdd(x.clone(), src.clone(), positions),
+        vec![x.clone(), src.clone()],
+    )
+}
+
+/// The slices of `x` at `indices` along `axis`, in that order. Indices may repeat.
+pub fn index_select(x: &Tensor, axis: usize, indices: &[usize]) -> Tensor {
+    let mut shape = x.shape();
+    assert!(
+        axis < shape.len(),
+        "axis {} out of range for tensor of shape {:?}",
+        axis,
+        shape
+    );
+    shape[axis] = indices.len();
+    let inner = shape[axis + 1..].iter().product::<usize>();
+    let outer = shape[..axis].iter().product::<usize>();
+    let mut index = Vec::with_capacity(outer * indices.len() * inner);
+    for _ in 0..outer {
+        for &i in indices {
+            index.extend(std::iter::repeat_n(i, inner));
+        }
+    }
+    gather(x, axis, &index, &shape)
+}
+
+/// Checks that `mask` covers the trailing axes of `shape`, so it can repeat
+/// over the leading ones.
+fn check_mask(shape: &[usize], mask: &[bool]) {
+    let covers = (0..=shape.len()).any(|d| shape[d..].iter().product::<usize>() == mask.len());
+    assert!(
+        covers,
+        "mask of {} elements does not match the trailing axes of shape {:?}",
+        mask.len(),
+        shape
+    );
+}
+
+/// The elements of `x` where `mask` is true, as a 1-D tensor.
+pub fn masked_select(x: &Tensor, mask: &[bool]) -> Tensor {
+    let shape = x.shape();
+    check_mask(&shape, mask);
+    let positions = (0..x.numel())
+        .filter(|&i| mask[i % mask.len()])
+        .collect::<Vec<_>>();
+    let out = gather_values(&x.contiguous(), &positions);
+    let len = positions.len();
+    Tensor::from_op(
+        out,
+        vec![len],
+        TensorOp::Gather(x.clone(), positions),
+        vec![x.clone()],
+    )
+}
+
+/// `x` with `value` wherever `mask` is true. A mask over the trailing axes
+/// repeats over the leading ones, e.g. one causal mask for every head.
+pub fn masked_fill(x: &Tensor, mask: &[bool], value: f32) -> Tensor {
+    let shape = x.shape();
+    check_mask(&shape, mask);
+    let out = x
+        .contiguous()
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| if mask[i % mask.len()] { value } else { v })
+        .collect();
+    Tensor::from_op(
+        out,
+        shape,
+        TensorOp::MaskedFill(x.clone(), mask.to_vec()),
+        vec![x.clone()],
+    )
+}
+
+pub(super) fn backward_gather(grad: &[f32], x: &Tensor, positions: &[usize]) {
+    let mut dx = vec![0.0; x.numel()];
+    scatter_add_values(&mut dx, positions, grad);
+    x.accumulate_grad(&dx);
+}
+
+pub(super) fn backward_scatter_add(grad: &[f32], x: &Tensor, src: &Tensor, positions: &[usize]) {
+    x.accumulate_grad(grad);
+    src.accumulate_grad(&gather_values(grad, positions));
+}
+
+pub(super) fn backward_masked_fill(grad: &[f32], x: &Tensor, mask: &[bool]) {
+    let dx = grad
+        .iter()
+        .enumerate()
+        .map(|(i, &g)| if mask[i % mask.len()] { 0.0 } else { g })
+        .collect::<Vec<_>>();
+    x.accumulate_grad(&dx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::tensor::{backward, matmul, reshape, sum};
+
+    #[test]
+    fn test_gather_rows() {
+        let x = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+        let g = gather(&x, 1, &[2, 0, 1, 1], &[2, 2]);
+        assert_eq!(g.data(), vec![3.0, 1.0, 5.0, 5.0]);
+        backward(&sum(&g, &[], false));
+        // the repeated index accumulates
+        assert_eq!(x.grad(), vec![1.0, 0.0, 1.0, 0.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn test_scatter_add_duplicates() {
+        let x = Tensor::zeros(&[4]);
+        let src = Tensor::new(vec![1.0, 2.0, 3.0], &[3]);
+        let s = scatter_add(&x, 0, &[1, 3, 1], &src);
+        assert_eq!(s.data(), vec![0.0, 4.0, 0.0, 2.0]);
+        let w = Tensor::new(vec![1.0, 10.0, 100.0, 1000.0], &[4, 1]);
+        let y = matmul(&reshape(&s, &[1, 4]), &w);
+        backward(&y);
+        assert_eq!(x.grad(), vec![1.0, 10.0, 100.0, 1000.0]);
+        assert_eq!(src.grad(), vec![10.0, 1000.0, 10.0]);
+    }
+
+    #[test]
+    fn test_index_select_and_masks() {
+        let table = Tensor::new(vec![0.0, 1.0, 10.0, 11.0, 20.0, 21.0], &[3, 2]);
+        let rows = index_select(&table, 0, &[2, 0, 2]);
+        assert_eq!(rows.shape(), vec![3, 2]);
+        assert_eq!(rows.data(), vec![20.0, 21.0, 0.0, 1.0, 20.0, 21.0]);
+        backward(&sum(&rows, &[], false));
+        assert_eq!(table.grad(), vec![1.0, 1.0, 0.0, 0.0, 2.0, 2.0]);
+
+        let x = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2]);
+        let picked = masked_select(&x, &[true, false, false, true]);
+        assert_eq!(picked.data(), vec![1.0, 4.0]);
+
+        // a row mask repeats over the leading axis
+        let filled = masked_fill(&x, &[false, true], -1.0);
+        assert_eq!(filled.data(), vec![1.0, -1.0, 3.0, -1.0]);
+        backward(&sum(&filled, &[], false));
+        assert_eq!(x.grad(), vec![1.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_gather_out_of_range() {
+        gather(&Tensor::zeros(&[2, 2]), 1, &[0, 2], &[2, 1]);
+    }
+}
@@ -0,0 +1,148 @@
+use super::kernel::{gemm, transpose};
+use super::{Tensor, TensorOp};
+
+/// Dimensions of a (possibly batched) matrix product.
+struct Dims {
+    batch: usize,
+    m: usize,
+    k: usize,
+    n: usize,
+    a_batched: bool,
+    b_batched: bool,
+}
+
+fn dims(a_shape: &[usize], b_shape: &[usize]) -> Dims {
+    assert!(
+        matches!(a_shape.len(), 2 | 3) && matches!(b_shape.len(), 2 | 3),
+        "matmul supports 2-D and batched 3-D tensors, got {:?} and {:?}",
+        a_shape,
+        b_shape
+    );
+    let a_batched = a_shape.len() == 3;
+    let b_batched = b_shape.len() == 3;
+    let (m, k) = (a_shape[a_shape.len() - 2], a_shape[a_shape.len() - 1]);
+    let (k2, n) = (b_shape[b_shape.len() - 2], b_shape[b_shape.len() - 1]);
+    assert_eq!(
+        k, k2,
+        "matmul inner dimensions differ: {:?} and {:?}",
+        a_shape, b_shape
+    );
+    let batch = match (a_batched, b_batched) {
+        (true, true) => {
+            assert_eq!(
+                a_shape[0], b_shape[0],
+                "matmul batch dimensions differ: {:?} and {:?}",
+                a_shape, b_shape
+            );
+            a_shape[0]
+        }
+        (true, false) => a_shape[0],
+        (false, true) => b_shape[0],
+        (false, false) => 1,
+    };
+    Dims {
+        batch,
+        m,
+        k,
+        n,
+        a_batched,
+        b_batched,
+    }
+}
+
+/// Matrix product of `[m, k] x [k, n]`, or the batched `[b, m, k] x [b, k, n]`.
+/// A 2-D operand is shared across every batch of a 3-D one, which is how a
+/// dense layer applies one weight matrix to a whole batch.
+pub fn matmul(a: &Tensor, b: &Tensor) -> Tensor {
+    let (a_shape, b_shape) = (a.shape(), b.shape());
+    let d = dims(&a_shape, &b_shape);
+    let (av, bv) = (a.contiguous(), b.contiguous());
+
+    let mut out = vec![0.0; d.batch * d.m * d.n];
+    for bi in 0..d.batch {
+        let a_off = if d.a_batched { bi * d.m * d.k } else { 0 };
+        let b_off = if d.b_batched { bi * d.k * d.n } else { 0 };
+        gemm(
+            d.m,
+            d.k,
+            d.n,
+            &av[a_off..a_off + d.m * d.k],
+            &bv[b_off..b_off + d.k * d.n],
+            &mut out[bi * d.m * d.n..(bi + 1) * d.m * d.n],
+        );
+    }
+
+    let shape = if d.a_batched || d.b_batched {
+        vec![d.batch, d.m, d.n]
+    } else {
+        vec![d.m, d.n]
+    };
+    Tensor::from_op(
+        out,
+        shape,
+        TensorOp::MatMul(a.clone(), b.clone()),
+        vec![a.clone(), b.clone()],
+    )
+}
+
+/// `dA = dC * B^T` and `dB = A^T * dC`, summed over the batch for a shared operand.
+pub(super) fn backward(grad: &[f32], a: &Tensor, b: &Tensor) {
+    let d = dims(&a.shape(), &b.shape());
+    let (av, bv) = (a.contiguous(), b.contiguous());
+
+    let mut da = vec![0.0; av.len()];
+    let mut db = vec![0.0; bv.len()];
+    for bi in 0..d.batch {
+        let a_off = if d.a_batched { bi * d.m * d.k } else { 0 };
+        let b_off = if d.b_batched { bi * d.k * d.n } else { 0 };
+        let g = &grad[bi * d.m * d.n..(bi + 1) * d.m * d.n];
+
+        let bt = transpose(d.k, d.n, &bv[b_off..b_off + d.k * d.n]);
+        gemm(d.m, d.n, d.k, g, &bt, &mut da[a_off..a_off + d.m * d.k]);
+
+        let at = transpose(d.m, d.k, &av[a_off..a_off + d.m * d.k]);
+        gemm(d.k, d.m, d.n, &at, g, &mut db[b_off..b_off + d.k * d.n]);
+    }
+    a.accumulate_grad(&da);
+    b.accumulate_grad(&db);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::tensor::backward;
+
+    #[test]
+    fn test_matmul_2d() {
+        let a = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+        let b = Tensor::new(vec![7.0, 8.0, 9.0, 10.0, 11.0, 12.0], &[3, 2]);
+        let c = matmul(&a, &b);
+        assert_eq!(c.shape(), vec![2, 2]);
+        assert_eq!(c.data(), vec![58.0, 64.0, 139.0, 154.0]);
+
+        backward(&c);
+        // dA = ones * B^T, dB = A^T * ones
+        assert_eq!(a.grad(), vec![15.0, 19.0, 23.0, 15.0, 19.0, 23.0]);
+        assert_eq!(b.grad(), vec![5.0, 5.0, 7.0, 7.0, 9.0, 9.0]);
+    }
+
+    #[test]
+    fn test_matmul_batched_shared_weight() {
+        let x = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 1, 2]);
+        let w = Tensor::new(vec![1.0, 0.0, 0.0, 1.0], &[2, 2]);
+        let y = matmul(&x, &w);
+        assert_eq!(y.shape(), vec![2, 1, 2]);
+        assert_eq!(y.data(), vec![1.0, 2.0, 3.0, 4.0]);
+
+        backward(&y);
+        assert_eq!(x.grad(), vec![1.0, 1.0, 1.0, 1.0]);
+        // the shared weight collects the gradient of both batches
+        assert_eq!(w.grad(), vec![4.0, 4.0, 6.0, 6.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "inner dimensions differ")]
+    fn test_matmul_shape_mismatch() {
+        matmul(&Tensor::zeros(&[2, 3]), &Tensor::zeros(&[2, 3]));
+    }
+}
@@ -0,0 +1,426 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+pub mod einsum;
+pub mod elementwise;
+pub mod index;
+pub mod kernel;
+pub mod matmul;
+pub mod norm;
+pub mod reduce;
+pub mod shape;
+
+pub use einsum::{einsum, EinsumError};
+pub use elementwise::{add, div, mul, sub};
+pub use index::{gather, index_select, masked_fill, masked_select, scatter_add};
+pub use matmul::matmul;
+pub use norm::{batch_norm, layer_norm, normalize, RunningStats};
+pub use reduce::{argmax, max, mean, min, std, sum, var};
+pub use shape::{
+    concat, permute, reshape, slice, split, squeeze, stack, transpose, unsqueeze, view,
+};
+
+/// An n-dimensional array of `f32` that records the operations applied to it,
+/// so gradients can flow back through whole blocks of numbers at once instead
+/// of one `Unit` per scalar.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Tensor(Rc<RefCell<_Tensor>>);
+
+#[derive(Clone)]
+pub enum TensorOp {
+    MatMul(Tensor, Tensor),
+    Sum(Tensor, Vec<usize>),
+    Mean(Tensor, Vec<usize>),
+    Max(Tensor, Vec<usize>),
+    Min(Tensor, Vec<usize>),
+    Var(Tensor, Vec<usize>, bool),
+    Std(Tensor, Vec<usize>, bool),
+    Reshape(Tensor),
+    Permute(Tensor, Vec<usize>),
+    Slice(Tensor, usize, usize, usize),
+    Concat(Vec<Tensor>, usize),
+    Gather(Tensor, Vec<usize>),
+    ScatterAdd(Tensor, Tensor, Vec<usize>),
+    MaskedFill(Tensor, Vec<bool>),
+    Einsum(Vec<Tensor>, einsum::Subscripts),
+    Add(Tensor, Tensor),
+    Sub(Tensor, Tensor),
+    Mul(Tensor, Tensor),
+    Div(Tensor, Tensor),
+    Normalize(Tensor, Vec<usize>, f32),
+}
+
+impl fmt::Debug for TensorOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TensorOp::MatMul(ref a, ref b) => {
+                write!(f, "MatMul {:?} {:?}", a.borrow().shape, b.borrow().shape)
+            }
+            TensorOp::Sum(ref x, ref axes) => write!(f, "Sum {:?} {:?}", x.borrow().shape, axes),
+            TensorOp::Mean(ref x, ref axes) => write!(f, "Mean {:?} {:?}", x.borrow().shape, axes),
+            TensorOp::Max(ref x, _) => write!(f, "Max {:?}", x.borrow().shape),
+            TensorOp::Min(ref x, _) => write!(f, "Min {:?}", x.borrow().shape),
+            TensorOp::Var(ref x, ref axes, _) => write!(f, "Var {:?} {:?}", x.borrow().shape, axes),
+            TensorOp::Std(ref x, ref axes, _) => write!(f, "Std {:?} {:?}", x.borrow().shape, axes),
+            TensorOp::Reshape(ref x) => write!(f, "Reshape {:?}", x.borrow().shape),
+            TensorOp::Permute(ref x, ref dims) => {
+                write!(f, "Permute {:?} {:?}", x.borrow().shape, dims)
+            }
+            TensorOp::Slice(ref x, axis, start, step) => write!(
+                f,
+                "Slice {:?} axis {} from {} step {}",
+                x.borrow().shape,
+                axis,
+                start,
+                step
+            ),
+            TensorOp::Concat(ref xs, axis) => write!(f, "Concat {} axis {}", xs.len(), axis),
+            TensorOp::Gather(ref x, ref positions) => {
+                write!(f, "Gather {:?} {}", x.borrow().shape, positions.len())
+            }
+            TensorOp::ScatterAdd(ref x, ref src, _) => {
+                write!(
+                    f,
+                    "ScatterAdd {:?} {:?}",
+                    x.borrow().shape,
+                    src.borrow().shape
+                )
+            }
+            TensorOp::MaskedFill(ref x, _) => write!(f, "MaskedFill {:?}", x.borrow().shape),
+            TensorOp::Einsum(ref xs, ref subscripts) => {
+                write!(f, "Einsum {} {:?}", xs.len(), subscripts)
+            }
+            TensorOp::Add(ref a, ref b)
+            | TensorOp::Sub(ref a, ref b)
+            | TensorOp::Mul(ref a, ref b)
+            | TensorOp::Div(ref a, ref b) => {
+                write!(f, "{} {:?} {:?}", self, a.borrow().shape, b.borrow().shape)
+            }
+            TensorOp::Normalize(ref x, ref axes, eps) => {
+                write!(f, "Normalize {:?} {:?} eps {}", x.borrow().shape, axes, eps)
+            }
+        }
+    }
+}
+
+impl fmt::Display for TensorOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TensorOp::MatMul(_, _) => write!(f, "MatMul"),
+            TensorOp::Sum(_, _) => write!(f, "Sum"),
+            TensorOp::Mean(_, _) => write!(f, "Mean"),
+            TensorOp::Max(_, _) => write!(f, "Max"),
+            TensorOp::Min(_, _) => write!(f, "Min"),
+            TensorOp::Var(_, _, _) => write!(f, "Var"),
+            TensorOp::Std(_, _, _) => write!(f, "Std"),
+            TensorOp::Reshape(_) => write!(f, "Reshape"),
+            TensorOp::Permute(_, _) => write!(f, "Permute"),
+            TensorOp::Slice(_, _, _, _) => write!(f, "Slice"),
+            TensorOp::Concat(_, _) => write!(f, "Concat"),
+            TensorOp::Gather(_, _) => write!(f, "Gather"),
+            TensorOp::ScatterAdd(_, _, _) => write!(f, "ScatterAdd"),
+            TensorOp::MaskedFill(_, _) => write!(f, "MaskedFill"),
+            TensorOp::Einsum(_, _) => write!(f, "Einsum"),
+            TensorOp::Add(_, _) => write!(f, "Add"),
+            TensorOp::Sub(_, _) => write!(f, "Sub"),
+            TensorOp::Mul(_, _) => write!(f, "Mul"),
+            TensorOp::Div(_, _) => write!(f, "Div"),
+            TensorOp::Normalize(_, _, _) => write!(f, "Normalize"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct _Tensor {
+    _id: usize,
+    /// Backing values, possibly shared with other views of the same data.
+    pub storage: Rc<Vec<f32>>,
+    pub shape: Vec<usize>,
+    pub strides: Vec<usize>,
+    pub offset: usize,
+    pub grad: Vec<f32>,
+    pub operation: Option<TensorOp>,
+    pub children: Vec<Tensor>,
+}
+
+impl Eq for _Tensor {}
+impl PartialEq for _Tensor {
+    fn eq(&self, other: &_Tensor) -> bool {
+        self._id == other._id
+    }
+}
+
+impl Hash for _Tensor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self._id.hash(state);
+    }
+}
+
+impl fmt::Debug for _Tensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("_Tensor")
+            .field("shape", &self.shape)
+            .field("strides", &self.strides)
+            .field("operation", &self.operation)
+            .field("children_size", &self.children.len())
+            .finish()
+    }
+}
+
+impl _Tensor {
+    pub fn new(
+        storage: Vec<f32>,
+        shape: Vec<usize>,
+        op: Option<TensorOp>,
+        children: Vec<Tensor>,
+    ) -> _Tensor {
+        let numel = shape.iter().product::<usize>();
+        assert_eq!(
+            storage.len(),
+            numel,
+            "tensor of shape {:?} needs {} values, got {}",
+            shape,
+            numel,
+            storage.len()
+        );
+        _Tensor {
+            _id: rand::random(),
+            storage: Rc::new(storage),
+            strides: shape::contiguous_strides(&shape),
+            offset: 0,
+            shape,
+            grad: vec![0.0; numel],
+            operation: op,
+            children,
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self._id
+    }
+
+    pub fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn self_back_propagation(&self) {
+        match self.operation {
+            Some(ref op) => match op {
+                TensorOp::MatMul(ref a, ref b) => matmul::backward(&self.grad, a, b),
+                TensorOp::Sum(ref x, ref axes) => reduce::backward_sum(&self.grad, x, axes),
+                TensorOp::Mean(ref x, ref axes) => reduce::backward_mean(&self.grad, x, axes),
+                TensorOp::Max(ref x, ref winners) | TensorOp::Min(ref x, ref winners) => {
+                    reduce::backward_select(&self.grad, x, winners)
+                }
+                TensorOp::Var(ref x, ref axes, unbiased) => {
+                    reduce::backward_var(&self.grad, x, axes, *unbiased)
+                }
+                TensorOp::Std(ref x, ref axes, unbiased) => {
+                    reduce::backward_std(&self.grad, &self.storage, x, axes, *unbiased)
+                }
+                TensorOp::Reshape(ref x) => x.accumulate_grad(&self.grad),
+                TensorOp::Permute(ref x, ref dims) => {
+                    shape::backward_permute(&self.grad, &self.shape, x, dims)
+                }
+                TensorOp::Slice(ref x, axis, start, step) => {
+                    shape::backward_slice(&self.grad, &self.shape, x, *axis, *start, *step)
+                }
+                TensorOp::Concat(ref xs, axis) => shape::backward_concat(&self.grad, xs, *axis),
+                TensorOp::Gather(ref x, ref positions) => {
+                    index::backward_gather(&self.grad, x, positions)
+                }
+                TensorOp::ScatterAdd(ref x, ref src, ref positions) => {
+                    index::backward_scatter_add(&self.grad, x, src, positions)
+                }
+                TensorOp::MaskedFill(ref x, ref mask) => {
+                    index::backward_masked_fill(&self.grad, x, mask)
+                }
+                TensorOp::Einsum(ref xs, ref subscripts) => {
+                    einsum::backward(&self.grad, xs, subscripts)
+                }
+                TensorOp::Add(_, _)
+                | TensorOp::Sub(_, _)
+                | TensorOp::Mul(_, _)
+                | TensorOp::Div(_, _) => elementwise::backward(&self.grad, &self.shape, op),
+                TensorOp::Normalize(ref x, ref axes, eps) => {
+                    norm::backward_normalize(&self.grad, &self.storage, x, axes, *eps)
+                }
+            },
+            None => {
+                // leaf tensor
+            }
+        }
+    }
+}
+
+impl Tensor {
+    pub fn new(data: Vec<f32>, shape: &[usize]) -> Tensor {
+        Tensor::from_inner(_Tensor::new(data, shape.to_vec(), None, vec![]))
+    }
+
+    pub fn zeros(shape: &[usize]) -> Tensor {
+        Tensor::new(vec![0.0; shape.iter().product()], shape)
+    }
+
+    pub fn ones(shape: &[usize]) -> Tensor {
+        Tensor::new(vec![1.0; shape.iter().product()], shape)
+    }
+
+    pub fn scalar(value: f32) -> Tensor {
+        Tensor::new(vec![value], &[])
+    }
+
+    pub(crate) fn from_op(
+        data: Vec<f32>,
+        shape: Vec<usize>,
+        op: TensorOp,
+        children: Vec<Tensor>,
+    ) -> Tensor {
+        Tensor::from_inner(_Tensor::new(data, shape, Some(op), children))
+    }
+
+    /// A tensor that reads the storage of `base` through new strides, without copying.
+    pub(crate) fn view_of(
+        base: &Tensor,
+        shape: Vec<usize>,
+        strides: Vec<usize>,
+        offset: usize,
+        op: TensorOp,
+    ) -> Tensor {
+        let numel = shape.iter().product();
+        Tensor::from_inner(_Tensor {
+            _id: rand::random(),
+            storage: base.borrow().storage.clone(),
+            shape,
+            strides,
+            offset,
+            grad: vec![0.0; numel],
+            operation: Some(op),
+            children: vec![base.clone()],
+        })
+    }
+
+    fn from_inner(t: _Tensor) -> Tensor {
+        Tensor(Rc::new(RefCell::new(t)))
+    }
+
+    pub fn shape(&self) -> Vec<usize> {
+        self.borrow().shape.clone()
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.borrow().shape.len()
+    }
+
+    pub fn numel(&self) -> usize {
+        self.borrow().numel()
+    }
+
+    /// The values in row-major order.
+    pub fn data(&self) -> Vec<f32> {
+        self.contiguous().to_vec()
+    }
+
+    pub fn grad(&self) -> Vec<f32> {
+        self.borrow().grad.clone()
+    }
+
+    pub fn item(&self) -> f32 {
+        assert_eq!(
+            self.numel(),
+            1,
+            "item() needs a single-element tensor, got shape {:?}",
+            self.shape()
+        );
+        self.contiguous()[0]
+    }
+
+    /// Whether the values sit in row-major order in the storage, so reshaping
+    /// is free.
+    pub fn is_contiguous(&self) -> bool {
+        let t = self.borrow();
+        t.strides == shape::contiguous_strides(&t.shape)
+    }
+
+    pub fn zero_grad(&self) {
+        self.borrow_mut().grad.iter_mut().for_each(|g| *g = 0.0);
+    }
+
+    /// Row-major values, shared with the tensor when no copy is needed.
+    pub(crate) fn contiguous(&self) -> Rc<Vec<f32>> {
+        let t = self.borrow();
+        if t.offset == 0 && t.storage.len() == t.numel() && self.is_contiguous() {
+            return t.storage.clone();
+        }
+        let values = shape::strided_indices(&t.shape, &t.strides, t.offset)
+            .into_iter()
+            .map(|i| t.storage[i])
+            .collect();
+        Rc::new(values)
+    }
+
+    pub(crate) fn accumulate_grad(&self, grad: &[f32]) {
+        let mut inner = self.borrow_mut();
+        debug_assert_eq!(inner.grad.len(), grad.len());
+        for (g, d) in inner.grad.iter_mut().zip(grad) {
+            *g += d;
+        }
+    }
+}
+
+impl Hash for Tensor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.borrow().hash(state)
+    }
+}
+
+impl Deref for Tensor {
+    type Target = Rc<RefCell<_Tensor>>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for Tensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Tensor {{ shape: {:?}, data: {:?}, operation: {:?} }}",
+            self.borrow().shape,
+            self.data(),
+            self.borrow().operation
+        )
+    }
+}
+
+/// Seeds the gradient of `t` with ones and propagates it to every tensor
+/// that contributed to it.
+pub fn backward(t: &Tensor) {
+    t.borrow_mut().grad.iter_mut().for_each(|g| *g = 1.0);
+    let mut sorted = topological_sort(t);
+    sorted.reverse();
+    for node in sorted.iter() {
+        node.borrow().self_back_propagation();
+    }
+}
+
+fn topological_sort(t: &Tensor) -> Vec<Tensor> {
+    let mut visited = HashSet::new();
+    let mut result = Vec::new();
+    visit(t, &mut visited, &mut result);
+    result
+}
+
+fn visit(node: &Tensor, visited: &mut HashSet<usize>, result: &mut Vec<Tensor>) {
+    if !visited.insert(node.borrow().id()) {
+        return;
+    }
+    for child in node.borrow().children.iter() {
+        visit(child, visited, result);
+    }
+    result.push(node.clone());
+}
@@ -0,0 +1,270 @@
+use super::elementwise::{add, div, mul, sub};
+use super::reduce::{means, normalize_axes, plan};
+use super::{Tensor, TensorOp};
+
+/// `(x - mean) / sqrt(var + eps)` over `axes`, using the biased variance. The
+/// statistics are part of the op, so the gradient accounts for every element
+/// moving the mean and variance of its group.
+pub fn normalize(x: &Tensor, axes: &[usize], eps: f32) -> Tensor {
+    let shape = x.shape();
+    let axes = normalize_axes(&shape, axes);
+    let p = plan(&shape, &axes, true);
+    let values = x.contiguous();
+    let m = means(&values, &p);
+    let centered = values
+        .iter()
+        .zip(p.index.iter())
+        .map(|(v, &o)| (v - m[o]) * (v - m[o]))
+        .collect::<Vec<_>>();
+    let inv_std = means(&centered, &p)
+        .into_iter()
+        .map(|v| 1.0 / (v + eps).sqrt())
+        .collect::<Vec<_>>();
+    let out = values
+        .iter()
+        .zip(p.index.iter())
+        .map(|(v, &o)| (v - m[o]) * inv_std[o])
+        .collect();
+    Tensor::from_op(
+        out,
+        shape,
+        TensorOp::Normalize(x.clone(), axes, eps),
+        vec![x.clone()],
+    )
+}
+
+/// Layer normalization over the last axis, with an optional per-feature gain
+/// and bias of shape `[features]`.
+pub fn layer_norm(x: &Tensor, weight: Option<&Tensor>, bias: Option<&Tensor>, eps: f32) -> Tensor {
+    assert!(x.ndim() > 0, "layer_norm needs at least one axis");
+    let y = normalize(x, &[x.ndim() - 1], eps);
+    affine(y, weight, bias)
+}
+
+fn affine(y: Tensor, weight: Option<&Tensor>, bias: Option<&Tensor>) -> Tensor {
+    let y = match weight {
+        Some(w) => mul(&y, w),
+        None => y,
+    };
+    match bias {
+        Some(b) => add(&y, b),
+        None => y,
+    }
+}
+
+/// Running estimates of the per-feature mean and variance kept by batch norm.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunningStats {
+    pub mean: Vec<f32>,
+    pub var: Vec<f32>,
+    /// weight of the newest batch: `running = (1 - momentum) * running + momentum * batch`
+    pub momentum: f32,
+}
+
+impl RunningStats {
+    pub fn new(num_features: usize, momentum: f32) -> Self {
+        RunningStats {
+            mean: vec![0.0; num_features],
+            var: vec![1.0; num_features],
+            momentum,
+        }
+    }
+
+    /// Folds in a batch mean and its unbiased variance.
+    pub fn update(&mut self, mean: &[f32], var: &[f32]) {
+        let m = self.momentum;
+        for (r, b) in self.mean.iter_mut().zip(mean.iter()) {
+            *r = (1.0 - m) * *r + m * b;
+        }
+        for (r, b) in self.var.iter_mut().zip(var.iter()) {
+            *r = (1.0 - m) * *r + m * b;
+        }
+    }
+}
+
+/// Batch normalization of `x` with shape `[batch, features]`. In training the
+/// batch statistics normalize `x` and are folded into `stats`; otherwise the
+/// running statistics are used as constants.
+pub fn batch_norm(
+    x: &Tensor,
+    stats: &mut RunningStats,
+    training: bool,
+    weight: Option<&Tensor>,
+    bias: Option<&Tensor>,
+    eps: f32,
+) -> Tensor {
+    let shape = x.shape();
+    assert_eq!(
+        shape.len(),
+        2,
+        "batch_norm expects [batch, features], got {:?}",
+        shape
+    );
+    let (n, c) = (shape[0], shape[1]);
+    assert_eq!(
+        c,
+        stats.mean.len(),
+        "batch_norm has {} features, input has {}",
+        stats.mean.len(),
+        c
+    );
+    let y = if training {
+        assert!(
+            n > 1,
+            "batch_norm needs more than one sample per batch in training"
+        );
+        let values = x.contiguous();
+        let mut mean = vec![0.0; c];
+        for row in values.chunks(c) {
+            mean.iter_mut()
+                .zip(row)
+                .for_each(|(m, v)| *m += v / n as f32);
+        }
+        let mut var = vec![0.0; c];
+        for row in values.chunks(c) {
+            for ((s, v), m) in var.iter_mut().zip(row).zip(mean.iter()) {
+                *s += (v - m) * (v - m) / (n - 1) as f32;
+            }
+        }
+        stats.update(&mean, &var);
+        normalize(x, &[0], eps)
+    } else {
+        let mean = Tensor::new(stats.mean.clone(), &[c]);
+        let std = stats.var.iter().map(|v| (v + eps).sqrt()).collect();
+        div(&sub(x, &mean), &Tensor::new(std, &[c]))
+    };
+    affine(y, weight, bias)
+}
+
+/// dx = (g - mean(g) - y * mean(g * y)) / sigma, with `y` the normalized output.
+pub(super) fn backward_normalize(grad: &[f32], out: &[f32], x: &Tensor, axes: &[usize], eps: f32) {
+    let p = plan(&x.shape(), axes, true);
+    let values = x.contiguous();
+    let m = means(&values, &p);
+    let centered = values
+        .iter()
+        .zip(p.index.iter())
+        .map(|(v, &o)| (v - m[o]) * (v - m[o]))
+        .collect::<Vec<_>>();
+    let inv_std = means(&centered, &p)
+        .into_iter()
+        .map(|v| 1.0 / (v + eps).sqrt())
+        .collect::<Vec<_>>();
+    let mean_g = means(grad, &p);
+    let gy = grad
+        .iter()
+        .zip(out.iter())
+        .map(|(g, y)| g * y)
+        .collect::<Vec<_>>();
+    let mean_gy = means(&gy, &p);
+    let dx = grad
+        .iter()
+        .zip(out.iter())
+        .zip(p.index.iter())
+        .map(|((g, y), &o)| (g - mean_g[o] - y * mean_gy[o]) * inv_std[o])
+        .collect::<Vec<_>>();
+    x.accumulate_grad(&dx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::tensor::{backward, sum};
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < 1e-3, "{:?} != {:?}", a, b);
+        }
+    }
+
+    /// Central differences of `f(x) . w` against the analytic gradient.
+    fn check_grad(values: Vec<f32>, shape: &[usize], f: impl Fn(&Tensor) -> Tensor) {
+        let x = Tensor::new(values.clone(), shape);
+        let weights = Tensor::new(
+            (0..values.len()).map(|i| (i as f32 * 0.7).sin()).collect(),
+            shape,
+        );
+        let objective = |t: &Tensor| sum(&mul(&f(t), &weights), &[], false);
+        backward(&objective(&x));
+        let h = 1e-2;
+        let numeric = (0..values.len())
+            .map(|i| {
+                let mut up = values.clone();
+                up[i] += h;
+                let mut down = values.clone();
+                down[i] -= h;
+                let up = objective(&Tensor::new(up, shape)).item();
+                let down = objective(&Tensor::new(down, shape)).item();
+                (up - down) / (2.0 * h)
+            })
+            .collect::<Vec<_>>();
+        assert_close(&x.grad(), &numeric);
+    }
+
+    #[test]
+    fn test_layer_norm_rows() {
+        let x = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, -2.0, 0.0, 2.0, 8.0], &[2, 4]);
+        let y = layer_norm(&x, None, None, 0.0).data();
+        for row in y.chunks(4) {
+            let m = row.iter().sum::<f32>() / 4.0;
+            let v = row.iter().map(|v| (v - m) * (v - m)).sum::<f32>() / 4.0;
+            assert!(m.abs() < 1e-5 && (v - 1.0).abs() < 1e-4);
+        }
+        check_grad(x.data(), &[2, 4], |t| layer_norm(t, None, None, 1e-5));
+    }
+
+    #[test]
+    fn test_layer_norm_affine_gradients() {
+        let x = Tensor::new(vec![0.5, -1.0, 2.0, 3.0, 1.0, -4.0], &[2, 3]);
+        let w = Tensor::new(vec![1.0, 2.0, 0.5], &[3]);
+        let b = Tensor::new(vec![0.0, 1.0, -1.0], &[3]);
+        let y = layer_norm(&x, Some(&w), Some(&b), 1e-5);
+        backward(&sum(&y, &[], false));
+        // each column sums the normalized values of both rows
+        let xhat = layer_norm(&x, None, None, 1e-5).data();
+        assert_close(
+            &w.grad(),
+            &[xhat[0] + xhat[3], xhat[1] + xhat[4], xhat[2] + xhat[5]],
+        );
+        assert_eq!(b.grad(), vec![2.0, 2.0, 2.0]);
+        check_grad(x.data(), &[2, 3], |t| {
+            layer_norm(t, Some(&w), Some(&b), 1e-5)
+        });
+    }
+
+    #[test]
+    fn test_batch_norm_train_and_eval() {
+        let x = Tensor::new(vec![1.0, 10.0, 3.0, 20.0, 5.0, 30.0], &[3, 2]);
+        let mut stats = RunningStats::new(2, 0.5);
+        let y = batch_norm(&x, &mut stats, true, None, None, 0.0).data();
+        assert_close(&y, &[-1.2247, -1.2247, 0.0, 0.0, 1.2247, 1.2247]);
+        assert_close(&stats.mean, &[1.5, 10.0]);
+        assert_close(&stats.var, &[2.5, 50.5]);
+
+        let y = batch_norm(&x, &mut stats, false, None, None, 0.0).data();
+        assert_close(&y[..2], &[(1.0 - 1.5) / 2.5f32.sqrt(), 0.0]);
+        assert_close(&stats.mean, &[1.5, 10.0]);
+
+        let mut stats = RunningStats::new(2, 0.1);
+        check_grad(x.data(), &[3, 2], |t| {
+            batch_norm(t, &mut stats.clone(), true, None, None, 1e-5)
+        });
+        stats.mean = vec![1.0, 2.0];
+        check_grad(x.data(), &[3, 2], |t| {
+            batch_norm(t, &mut stats.clone(), false, None, None, 1e-5)
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "batch_norm has 3 features, input has 2")]
+    fn test_batch_norm_feature_mismatch() {
+        batch_norm(
+            &Tensor::zeros(&[4, 2]),
+            &mut RunningStats::new(3, 0.1),
+            true,
+            None,
+            None,
+            1e-5,
+        );
+    }
+}
@@ -0,0 +1,352 @@
+use super::{Tensor, TensorOp};
+
+/// How the elements of an input tensor collapse onto the output of a reduction.
+pub(super) struct Plan {
+    pub(super) out_shape: Vec<usize>,
+    /// output position of every input element, in row-major input order
+    pub(super) index: Vec<usize>,
+    /// number of input elements folded into each output element
+    pub(super) count: usize,
+    pub(super) out_len: usize,
+}
+
+/// Validates `axes` against `shape`. An empty slice means every axis.
+pub(super) fn normalize_axes(shape: &[usize], axes: &[usize]) -> Vec<usize> {
+    if axes.is_empty() {
+        return (0..shape.len()).collect();
+    }
+    let mut out = axes.to_vec();
+    out.sort_unstable();
+    out.dedup();
+    assert_eq!(out.len(), axes.len(), "duplicate axes in {:?}", axes);
+    if let Some(&last) = out.last() {
+        assert!(
+            last < shape.len(),
+            "axis {} out of range for tensor of shape {:?}",
+            last,
+            shape
+        );
+    }
+    out
+}
+
+pub(super) fn plan(shape: &[usize], axes: &[usize], keepdim: bool) -> Plan {
+    let reduced = (0..shape.len())
+        .map(|d| axes.contains(&d))
+        .collect::<Vec<_>>();
+
+    let mut out_strides = vec![0; shape.len()];
+    let mut out_len = 1;
+    for d in (0..shape.len()).rev() {
+        if !reduced[d] {
+            out_strides[d] = out_len;
+            out_len *= shape[d];
+        }
+    }
+
+    let numel = shape.iter().product::<usize>();
+    let mut index = Vec::with_capacity(numel);
+    let mut coord = vec![0; shape.len()];
+    let mut o = 0;
+    for _ in 0..numel {
+        index.push(o);
+        // advance the multi-index like an odometer, keeping `o` in step
+        for d in (0..shape.len()).rev() {
+            coord[d] += 1;
+            o += out_strides[d];
+            if coord[d] < shape[d] {
+                break;
+            }
+            o -= out_strides[d] * coord[d];
+            coord[d] = 0;
+        }
+    }
+
+    let out_shape = shape
+        .iter()
+        .enumerate()
+        .filter_map(|(d, &n)| match (reduced[d], keepdim) {
+            (false, _) => Some(n),
+            (true, true) => Some(1),
+            (true, false) => None,
+        })
+        .collect();
+    let count = axes.iter().map(|&d| shape[d]).product();
+    Plan {
+        out_shape,
+        index,
+        count,
+        out_len,
+    }
+}
+
+/// Sum over `axes` (all axes if empty). With `keepdim` the reduced axes stay
+/// as size 1, so the result broadcasts back against the input.
+pub fn sum(x: &Tensor, axes: &[usize], keepdim: bool) -> Tensor {
+    let shape = x.shape();
+    let axes = normalize_axes(&shape, axes);
+    let p = plan(&shape, &axes, keepdim);
+    let mut out = vec![0.0; p.out_len];
+    for (v, &o) in x.contiguous().iter().zip(p.index.iter()) {
+        out[o] += v;
+    }
+    Tensor::from_op(
+        out,
+        p.out_shape,
+        TensorOp::Sum(x.clone(), axes),
+        vec![x.clone()],
+    )
+}
+
+pub fn mean(x: &Tensor, axes: &[usize], keepdim: bool) -> Tensor {
+    let shape = x.shape();
+    let axes = normalize_axes(&shape, axes);
+    let p = plan(&shape, &axes, keepdim);
+    let mut out = vec![0.0; p.out_len];
+    for (v, &o) in x.contiguous().iter().zip(p.index.iter()) {
+        out[o] += v;
+    }
+    out.iter_mut().for_each(|v| *v /= p.count as f32);
+    Tensor::from_op(
+        out,
+        p.out_shape,
+        TensorOp::Mean(x.clone(), axes),
+        vec![x.clone()],
+    )
+}
+
+/// Flat input position of the winning element of every output, comparing with `better`.
+fn select(x: &Tensor, p: &Plan, better: fn(f32, f32) -> bool) -> Vec<usize> {
+    assert!(
+        p.count > 0,
+        "cannot reduce over an empty axis of {:?}",
+        x.shape()
+    );
+    let values = x.contiguous();
+    let mut winner: Vec<Option<usize>> = vec![None; p.out_len];
+    for (i, &o) in p.index.iter().enumerate() {
+        match winner[o] {
+            Some(w) if !better(values[i], values[w]) => {}
+            _ => winner[o] = Some(i),
+        }
+    }
+    winner.into_iter().map(|w| w.unwrap()).collect()
+}
+
+/// Maximum over `axes`. The gradient goes to the winning element only; on a
+/// tie the first one in row-major order wins.
+pub fn max(x: &Tensor, axes: &[usize], keepdim: bool) -> Tensor {
+    let shape = x.shape();
+    let axes = normalize_axes(&shape, axes);
+    let p = plan(&shape, &axes, keepdim);
+    let winners = select(x, &p, |a, b| a > b);
+    let values = x.contiguous();
+    let out = winners.iter().map(|&w| values[w]).collect();
+    Tensor::from_op(
+        out,
+        p.out_shape,
+        TensorOp::Max(x.clone(), winners),
+        vec![x.clone()],
+    )
+}
+
+pub fn min(x: &Tensor, axes: &[usize], keepdim: bool) -> Tensor {
+    let shape = x.shape();
+    let axes = normalize_axes(&shape, axes);
+    let p = plan(&shape, &axes, keepdim);
+    let winners = select(x, &p, |a, b| a < b);
+    let values = x.contiguous();
+    let out = winners.iter().map(|&w| values[w]).collect();
+    Tensor::from_op(
+        out,
+        p.out_shape,
+        TensorOp::Min(x.clone(), winners),
+        vec![x.clone()],
+    )
+}
+
+/// Position of the largest element along `axis`, for every other position of
+/// `x` in row-major order. Not differentiable.
+pub fn argmax(x: &Tensor, axis: usize) -> Vec<usize> {
+    let shape = x.shape();
+    let axes = normalize_axes(&shape, &[axis]);
+    let p = plan(&shape, &axes, false);
+    let stride = shape[axis + 1..].iter().product::<usize>();
+    select(x, &p, |a, b| a > b)
+        .into_iter()
+        .map(|w| (w / stride) % shape[axis])
+        .collect()
+}
+
+pub(super) fn means(values: &[f32], p: &Plan) -> Vec<f32> {
+    let mut m = vec![0.0; p.out_len];
+    for (v, &o) in values.iter().zip(p.index.iter()) {
+        m[o] += v;
+    }
+    m.iter_mut().for_each(|v| *v /= p.count as f32);
+    m
+}
+
+fn divisor(p: &Plan, unbiased: bool) -> f32 {
+    if unbiased {
+        p.count as f32 - 1.0
+    } else {
+        p.count as f32
+    }
+}
+
+/// Variance over `axes`, dividing by `n - 1` when `unbiased` and by `n` otherwise.
+pub fn var(x: &Tensor, axes: &[usize], unbiased: bool, keepdim: bool) -> Tensor {
+    let shape = x.shape();
+    let axes = normalize_axes(&shape, axes);
+    let p = plan(&shape, &axes, keepdim);
+    let values = x.contiguous();
+    let m = means(&values, &p);
+    let mut out = vec![0.0; p.out_len];
+    for (v, &o) in values.iter().zip(p.index.iter()) {
+        out[o] += (v - m[o]) * (v - m[o]);
+    }
+    let n = divisor(&p, unbiased);
+    out.iter_mut().for_each(|v| *v /= n);
+    Tensor::from_op(
+        out,
+        p.out_shape,
+        TensorOp::Var(x.clone(), axes, unbiased),
+        vec![x.clone()],
+    )
+}
+
+pub fn std(x: &Tensor, axes: &[usize], unbiased: bool, keepdim: bool) -> Tensor {
+    let v = var(x, axes, unbiased, keepdim);
+    let out = v.contiguous().iter().map(|v| v.sqrt()).collect();
+    let axes = normalize_axes(&x.shape(), axes);
+    Tensor::from_op(
+        out,
+        v.shape(),
+        TensorOp::Std(x.clone(), axes, unbiased),
+        vec![x.clone()],
+    )
+}
+
+pub(super) fn backward_sum(grad: &[f32], x: &Tensor, axes: &[usize]) {
+    let p = plan(&x.shape(), axes, true);
+    let dx = p.index.iter().map(|&o| grad[o]).collect::<Vec<_>>();
+    x.accumulate_grad(&dx);
+}
+
+pub(super) fn backward_mean(grad: &[f32], x: &Tensor, axes: &[usize]) {
+    let p = plan(&x.shape(), axes, true);
+    let n = p.count as f32;
+    let dx = p.index.iter().map(|&o| grad[o] / n).collect::<Vec<_>>();
+    x.accumulate_grad(&dx);
+}
+
+pub(super) fn backward_select(grad: &[f32], x: &Tensor, winners: &[usize]) {
+    let mut dx = vec![0.0; x.numel()];
+    for (g, &w) in grad.iter().zip(winners.iter()) {
+        dx[w] += g;
+    }
+    x.accumulate_grad(&dx);
+}
+
+/// d var / d x_i = 2 (x_i - mean) / n
+pub(super) fn backward_var(grad: &[f32], x: &Tensor, axes: &[usize], unbiased: bool) {
+    let p = plan(&x.shape(), axes, true);
+    let values = x.contiguous();
+    let m = means(&values, &p);
+    let n = divisor(&p, unbiased);
+    let dx = values
+        .iter()
+        .zip(p.index.iter())
+        .map(|(v, &o)| grad[o] * 2.0 * (v - m[o]) / n)
+        .collect::<Vec<_>>();
+    x.accumulate_grad(&dx);
+}
+
+/// d std / d x_i = (x_i - mean) / (n * std)
+pub(super) fn backward_std(grad: &[f32], out: &[f32], x: &Tensor, axes: &[usize], unbiased: bool) {
+    let p = plan(&x.shape(), axes, true);
+    let values = x.contiguous();
+    let m = means(&values, &p);
+    let n = divisor(&p, unbiased);
+    let dx = values
+        .iter()
+        .zip(p.index.iter())
+        .map(|(v, &o)| grad[o] * (v - m[o]) / (n * out[o]))
+        .collect::<Vec<_>>();
+    x.accumulate_grad(&dx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::tensor::backward;
+
+    fn close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < 1e-5, "{:?} vs {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_sum_and_mean_keepdim() {
+        let x = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+        let s = sum(&x, &[1], true);
+        assert_eq!(s.shape(), vec![2, 1]);
+        assert_eq!(s.data(), vec![6.0, 15.0]);
+
+        let m = mean(&x, &[0], false);
+        assert_eq!(m.shape(), vec![3]);
+        assert_eq!(m.data(), vec![2.5, 3.5, 4.5]);
+        backward(&m);
+        assert_eq!(x.grad(), vec![0.5; 6]);
+
+        let all = sum(&x, &[], false);
+        assert_eq!(all.shape(), Vec::<usize>::new());
+        assert_eq!(all.item(), 21.0);
+    }
+
+    #[test]
+    fn test_max_routes_gradient_to_winner() {
+        let x = Tensor::new(vec![1.0, 9.0, 3.0, 7.0, 5.0, 6.0], &[2, 3]);
+        let m = max(&x, &[1], false);
+        assert_eq!(m.data(), vec![9.0, 7.0]);
+        backward(&m);
+        assert_eq!(x.grad(), vec![0.0, 1.0, 0.0, 1.0, 0.0, 0.0]);
+
+        let y = Tensor::new(vec![1.0, 9.0, 3.0, 7.0, 5.0, 6.0], &[2, 3]);
+        let n = min(&y, &[0], true);
+        assert_eq!(n.shape(), vec![1, 3]);
+        assert_eq!(n.data(), vec![1.0, 5.0, 3.0]);
+        assert_eq!(argmax(&y, 1), vec![1, 0]);
+        assert_eq!(argmax(&y, 0), vec![1, 0, 1]);
+    }
+
+    #[test]
+    fn test_var_std_gradients() {
+        let x = Tensor::new(vec![1.0, 2.0, 4.0, 7.0], &[4]);
+        let v = var(&x, &[0], true, false);
+        // mean 3.5, squared deviations 6.25 + 2.25 + 0.25 + 12.25 = 21
+        close(&v.data(), &[7.0]);
+        backward(&v);
+        close(&x.grad(), &[-5.0 / 3.0, -1.0, 1.0 / 3.0, 7.0 / 3.0]);
+
+        let y = Tensor::new(vec![1.0, 2.0, 4.0, 7.0], &[4]);
+        let s = std(&y, &[0], false, false);
+        let sd = (21.0f32 / 4.0).sqrt();
+        close(&s.data(), &[sd]);
+        backward(&s);
+        let expected = [1.0f32, 2.0, 4.0, 7.0]
+            .iter()
+            .map(|v| (v - 3.5) / (4.0 * sd))
+            .collect::<Vec<_>>();
+        close(&y.grad(), &expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_bad_axis() {
+        sum(&Tensor::zeros(&[2, 2]), &[2], false);
+    }
+}
@@ -0,0 +1,384 @@
+use super::{Tensor, TensorOp};
+
+/// Row-major strides for `shape`.
+pub(crate) fn contiguous_strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for d in (0..shape.len().saturating_sub(1)).rev() {
+        strides[d] = strides[d + 1] * shape[d + 1];
+    }
+    strides
+}
+
+/// Storage position of every element of a strided layout, in row-major order.
+pub(crate) fn strided_indices(shape: &[usize], strides: &[usize], offset: usize) -> Vec<usize> {
+    let numel = shape.iter().product::<usize>();
+    let mut out = Vec::with_capacity(numel);
+    let mut coord = vec![0; shape.len()];
+    let mut pos = offset;
+    for _ in 0..numel {
+        out.push(pos);
+        for d in (0..shape.len()).rev() {
+            coord[d] += 1;
+            pos += strides[d];
+            if coord[d] < shape[d] {
+                break;
+            }
+            pos -= strides[d] * coord[d];
+            coord[d] = 0;
+        }
+    }
+    out
+}
+
+fn check_axis(shape: &[usize], axis: usize) {
+    assert!(
+        axis < shape.len(),
+        "axis {} out of range for tensor of shape {:?}",
+        axis,
+        shape
+    );
+}
+
+/// Same values under a new shape. Shares storage when `x` is contiguous and
+/// copies otherwise.
+pub fn reshape(x: &Tensor, shape: &[usize]) -> Tensor {
+    let old = x.shape();
+    assert_eq!(
+        old.iter().product::<usize>(),
+        shape.iter().product::<usize>(),
+        "cannot reshape {:?} into {:?}",
+        old,
+        shape
+    );
+    if x.is_contiguous() {
+        let offset = x.borrow().offset;
+        return Tensor::view_of(
+            x,
+            shape.to_vec(),
+            contiguous_strides(shape),
+            offset,
+            TensorOp::Reshape(x.clone()),
+        );
+    }
+    Tensor::from_op(
+        x.data(),
+        shape.to_vec(),
+        TensorOp::Reshape(x.clone()),
+        vec![x.clone()],
+    )
+}
+
+/// Like [`reshape`], but panics rather than copying a non-contiguous tensor.
+pub fn view(x: &Tensor, shape: &[usize]) -> Tensor {
+    assert!(
+        x.is_contiguous(),
+        "view of a non-contiguous tensor of shape {:?}, use reshape instead",
+        x.shape()
+    );
+    reshape(x, shape)
+}
+
+/// Reorders the axes of `x` so that axis `d` of the result is axis `dims[d]`
+/// of the input. Never copies.
+pub fn permute(x: &Tensor, dims: &[usize]) -> Tensor {
+    let (shape, strides, offset) = {
+        let t = x.borrow();
+        (t.shape.clone(), t.strides.clone(), t.offset)
+    };
+    let mut seen = vec![false; shape.len()];
+    for &d in dims {
+        check_axis(&shape, d);
+        seen[d] = true;
+    }
+    assert!(
+        dims.len() == shape.len() && seen.iter().all(|&s| s),
+        "{:?} is not a permutation of the axes of {:?}",
+        dims,
+        shape
+    );
+    Tensor::view_of(
+        x,
+        dims.iter().map(|&d| shape[d]).collect(),
+        dims.iter().map(|&d| strides[d]).collect(),
+        offset,
+        TensorOp::Permute(x.clone(), dims.to_vec()),
+    )
+}
+
+pub fn transpose(x: &Tensor, a: usize, b: usize) -> Tensor {
+    let mut dims = (0..x.ndim()).collect::<Vec<_>>();
+    check_axis(&x.shape(), a);
+    check_axis(&x.shape(), b);
+    dims.swap(a, b);
+    permute(x, &dims)
+}
+
+/// Removes the size-1 axis `axis`.
+pub fn squeeze(x: &Tensor, axis: usize) -> Tensor {
+    let (mut shape, mut strides, offset) = {
+        let t = x.borrow();
+        (t.shape.clone(), t.strides.clone(), t.offset)
+    };
+    check_axis(&shape, axis);
+    assert_eq!(
+        shape[axis], 1,
+        "cannot squeeze axis {} of shape {:?}",
+        axis, shape
+    );
+    shape.remove(axis);
+    strides.remove(axis);
+    Tensor::view_of(x, shape, strides, offset, TensorOp::Reshape(x.clone()))
+}
+
+/// Inserts a size-1 axis at position `axis`.
+pub fn unsqueeze(x: &Tensor, axis: usize) -> Tensor {
+    let (mut shape, mut strides, offset) = {
+        let t = x.borrow();
+        (t.shape.clone(), t.strides.clone(), t.offset)
+    };
+    assert!(
+        axis <= shape.len(),
+        "cannot unsqueeze axis {} of shape {:?}",
+        axis,
+        shape
+    );
+    let stride = if axis < shape.len() {
+        shape[axis] * strides[axis]
+    } else {
+        1
+    };
+    shape.insert(axis, 1);
+    strides.insert(axis, stride);
+    Tensor::view_of(x, shape, strides, offset, TensorOp::Reshape(x.clone()))
+}
+
+/// Elements `start, start + step, ...` below `end` along `axis`. Never copies.
+pub fn slice(x: &Tensor, axis: usize, start: usize, end: usize, step: usize) -> Tensor {
+    let (mut shape, mut strides, offset) = {
+        let t = x.borrow();
+        (t.shape.clone(), t.strides.clone(), t.offset)
+    };
+    check_axis(&shape, axis);
+    assert!(step > 0, "slice step must be positive");
+    assert!(
+        start <= end && end <= shape[axis],
+        "slice {}..{} out of range for axis {} of shape {:?}",
+        start,
+        end,
+        axis,
+        shape
+    );
+    let offset = offset + start * strides[axis];
+    shape[axis] = (end - start).div_ceil(step);
+    strides[axis] *= step;
+    Tensor::view_of(
+        x,
+        shape,
+        strides,
+        offset,
+        TensorOp::Slice(x.clone(), axis, start, step),
+    )
+}
+
+/// Cuts `x` along `axis` into consecutive pieces of the given sizes.
+pub fn split(x: &Tensor, sizes: &[usize], axis: usize) -> Vec<Tensor> {
+    let shape = x.shape();
+    check_axis(&shape, axis);
+    assert_eq!(
+        sizes.iter().sum::<usize>(),
+        shape[axis],
+        "split sizes {:?} do not add up to axis {} of shape {:?}",
+        sizes,
+        axis,
+        shape
+    );
+    let mut start = 0;
+    sizes
+        .iter()
+        .map(|&n| {
+            let piece = slice(x, axis, start, start + n, 1);
+            start += n;
+            piece
+        })
+        .collect()
+}
+
+/// Joins tensors along an existing axis. All other axes must match.
+pub fn concat(xs: &[Tensor], axis: usize) -> Tensor {
+    assert!(!xs.is_empty(), "concat needs at least one tensor");
+    let first = xs[0].shape();
+    check_axis(&first, axis);
+    for x in xs.iter() {
+        let shape = x.shape();
+        let compatible = shape.len() == first.len()
+            && (0..shape.len()).all(|d| d == axis || shape[d] == first[d]);
+        assert!(
+            compatible,
+            "cannot concat shapes {:?} and {:?} along axis {}",
+            first, shape, axis
+        );
+    }
+
+    let outer = first[..axis].iter().product::<usize>();
+    let inner = first[axis + 1..].iter().product::<usize>();
+    let values = xs.iter().map(|x| x.contiguous()).collect::<Vec<_>>();
+    let mut out = Vec::with_capacity(values.iter().map(|v| v.len()).sum());
+    for o in 0..outer {
+        for (x, v) in xs.iter().zip(values.iter()) {
+            let chunk = x.borrow().shape[axis] * inner;
+            out.extend_from_slice(&v[o * chunk..(o + 1) * chunk]);
+        }
+    }
+
+    let mut shape = first;
+    shape[axis] = xs.iter().map(|x| x.borrow().shape[axis]).sum();
+    Tensor::from_op(out, shape, TensorOp::Concat(xs.to_vec(), axis), xs.to_vec())
+}
+
+/// Joins tensors of identical shape along a new axis.
+pub fn stack(xs: &[Tensor], axis: usize) -> Tensor {
+    let expanded = xs.iter().map(|x| unsqueeze(x, axis)).collect::<Vec<_>>();
+    concat(&expanded, axis)
+}
+
+pub(super) fn backward_permute(grad: &[f32], out_shape: &[usize], x: &Tensor, dims: &[usize]) {
+    let xs = contiguous_strides(&x.shape());
+    let strides = dims.iter().map(|&d| xs[d]).collect::<Vec<_>>();
+    scatter(grad, &strided_indices(out_shape, &strides, 0), x);
+}
+
+pub(super) fn backward_slice(
+    grad: &[f32],
+    out_shape: &[usize],
+    x: &Tensor,
+    axis: usize,
+    start: usize,
+    step: usize,
+) {
+    let mut strides = contiguous_strides(&x.shape());
+    let offset = start * strides[axis];
+    strides[axis] *= step;
+    scatter(grad, &strided_indices(out_shape, &strides, offset), x);
+}
+
+pub(super) fn backward_concat(grad: &[f32], xs: &[Tensor], axis: usize) {
+    let shape = xs[0].shape();
+    let outer = shape[..axis].iter().product::<usize>();
+    let inner = shape[axis + 1..].iter().product::<usize>();
+    let mut grads = xs
+        .iter()
+        .map(|x| Vec::with_capacity(x.numel()))
+        .collect::<Vec<_>>();
+    let mut pos = 0;
+    for _ in 0..outer {
+        for (x, g) in xs.iter().zip(grads.iter_mut()) {
+            let chunk = x.borrow().shape[axis] * inner;
+            g.extend_from_slice(&grad[pos..pos + chunk]);
+            pos += chunk;
+        }
+    }
+    for (x, g) in xs.iter().zip(grads.iter()) {
+        x.accumulate_grad(g);
+    }
+}
+
+fn scatter(grad: &[f32], indices: &[usize], x: &Tensor) {
+    let mut dx = vec![0.0; x.numel()];
+    for (g, &i) in grad.iter().zip(indices.iter()) {
+        dx[i] += g;
+    }
+    x.accumulate_grad(&dx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::tensor::{backward, matmul, sum};
+    use std::rc::Rc;
+
+    fn arange(shape: &[usize]) -> Tensor {
+        let n = shape.iter().product::<usize>();
+        Tensor::new((0..n).map(|v| v as f32).collect(), shape)
+    }
+
+    #[test]
+    fn test_views_share_storage() {
+        let x = arange(&[2, 3]);
+        let r = reshape(&x, &[3, 2]);
+        let t = transpose(&x, 0, 1);
+        assert!(Rc::ptr_eq(&x.borrow().storage, &r.borrow().storage));
+        assert!(Rc::ptr_eq(&x.borrow().storage, &t.borrow().storage));
+        assert!(!t.is_contiguous());
+        assert_eq!(t.shape(), vec![3, 2]);
+        assert_eq!(t.data(), vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+        // reshaping a transposed view has to copy
+        assert_eq!(reshape(&t, &[6]).data(), vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+    }
+
+    #[test]
+    fn test_permute_backward() {
+        let x = arange(&[2, 3, 4]);
+        let p = permute(&x, &[2, 0, 1]);
+        assert_eq!(p.shape(), vec![4, 2, 3]);
+        let w = arange(&[4 * 2 * 3]);
+        let y = sum(
+            &matmul(&reshape(&p, &[1, 24]), &reshape(&w, &[24, 1])),
+            &[],
+            false,
+        );
+        backward(&y);
+        // element (i, j, k) of x sits at (k, i, j) in p, which is weighted by k * 6 + i * 3 + j
+        let mut expected = Vec::new();
+        for i in 0..2 {
+            for j in 0..3 {
+                for k in 0..4 {
+                    expected.push((k * 6 + i * 3 + j) as f32);
+                }
+            }
+        }
+        assert_eq!(x.grad(), expected);
+    }
+
+    #[test]
+    fn test_slice_and_split() {
+        let x = arange(&[2, 5]);
+        let s = slice(&x, 1, 1, 5, 2);
+        assert_eq!(s.shape(), vec![2, 2]);
+        assert_eq!(s.data(), vec![1.0, 3.0, 6.0, 8.0]);
+        backward(&sum(&s, &[], false));
+        assert_eq!(
+            x.grad(),
+            vec![0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0]
+        );
+
+        let parts = split(&x, &[2, 3], 1);
+        assert_eq!(parts[0].data(), vec![0.0, 1.0, 5.0, 6.0]);
+        assert_eq!(parts[1].data(), vec![2.0, 3.0, 4.0, 7.0, 8.0, 9.0]);
+    }
+
+    #[test]
+    fn test_concat_stack_squeeze() {
+        let a = arange(&[2, 1]);
+        let b = Tensor::new(vec![7.0, 8.0, 9.0, 10.0], &[2, 2]);
+        let c = concat(&[a.clone(), b.clone()], 1);
+        assert_eq!(c.shape(), vec![2, 3]);
+        assert_eq!(c.data(), vec![0.0, 7.0, 8.0, 1.0, 9.0, 10.0]);
+        let w = Tensor::new(vec![1.0, 2.0, 3.0], &[3, 1]);
+        backward(&sum(&matmul(&c, &w), &[], false));
+        assert_eq!(a.grad(), vec![1.0, 1.0]);
+        assert_eq!(b.grad(), vec![2.0, 3.0, 2.0, 3.0]);
+
+        let s = stack(&[arange(&[3]), arange(&[3])], 0);
+        assert_eq!(s.shape(), vec![2, 3]);
+        let u = unsqueeze(&s, 2);
+        assert_eq!(u.shape(), vec![2, 3, 1]);
+        assert!(u.is_contiguous());
+        assert_eq!(squeeze(&u, 2).shape(), vec![2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot concat")]
+    fn test_concat_mismatch() {
+        concat(&[arange(&[2, 2]), arange(&[3, 3])], 0);
+    }
+}
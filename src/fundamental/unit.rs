@@ -8,6 +8,9 @@ pub struct _Unit {
     _id: usize,
     pub data: f32,
     pub grad: f32,
+    /// Whether gradients are computed for this unit. Results of operations
+    /// need them when any input does.
+    pub requires_grad: bool,
     pub operation: Option<Operation>,
     pub children: Vec<Unit>,
 }
@@ -30,6 +33,7 @@ impl fmt::Debug for _Unit {
         f.debug_struct("_Unit")
             .field("data", &self.data)
             .field("grad", &self.grad)
+            .field("requires_grad", &self.requires_grad)
             .field("operation", &self.operation)
             .field("children_size", &self.children.len())
             .finish()
@@ -49,6 +53,36 @@ impl fmt::Display for _Unit {
     }
 }
 
+/// Logistic function, written so that neither branch can overflow.
+pub fn sigmoid(x: f32) -> f32 {
+    if x >= 0.0 {
+        1.0 / (1.0 + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (1.0 + e)
+    }
+}
+
+const GELU_C: f32 = 0.797_884_6; // sqrt(2 / pi)
+
+/// GELU with the tanh approximation used by GPT-2.
+pub fn gelu(x: f32) -> f32 {
+    0.5 * x * (1.0 + (GELU_C * (x + 0.044715 * x * x * x)).tanh())
+}
+
+fn gelu_grad(x: f32) -> f32 {
+    let t = (GELU_C * (x + 0.044715 * x * x * x)).tanh();
+    0.5 * (1.0 + t) + 0.5 * x * (1.0 - t * t) * GELU_C * (1.0 + 3.0 * 0.044715 * x * x)
+}
+
+/// Adds `grad` to the gradient of `u`, unless `u` is frozen.
+fn accumulate(u: &Unit, grad: f32) {
+    let mut u = u.borrow_mut();
+    if u.requires_grad {
+        u.grad += grad;
+    }
+}
+
 pub fn new_unit(data: f32) -> Unit {
     Unit::new(_Unit::from(data))
 }
@@ -58,6 +92,7 @@ impl _Unit {
         _Unit {
             _id: rand::random(),
             data,
+            requires_grad: children.iter().any(|c| c.borrow().requires_grad),
             operation: op,
             children,
             grad: 0.0,
@@ -69,6 +104,7 @@ impl _Unit {
             _id: rand::random(),
             data,
             grad: 0.0,
+            requires_grad: true,
             operation: None,
             children: vec![],
         }
@@ -79,32 +115,102 @@ impl _Unit {
     }
 
     pub fn self_back_propagation(&mut self) {
+        if !self.requires_grad {
+            return;
+        }
         match self.operation {
             Some(ref op) => {
                 match op {
                     Operation::Add(ref a, ref b) => {
-                        a.borrow_mut().grad += self.grad;
-                        b.borrow_mut().grad += self.grad;
+                        accumulate(a, self.grad);
+                        accumulate(b, self.grad);
                     }
                     Operation::Mul(ref a, ref b) => {
-                        a.borrow_mut().grad += self.grad * b.borrow().data;
-                        b.borrow_mut().grad += self.grad * a.borrow().data;
+                        // read both first: `a` and `b` may be the same unit
+                        let (ad, bd) = (a.borrow().data, b.borrow().data);
+                        accumulate(a, self.grad * bd);
+                        accumulate(b, self.grad * ad);
                     }
                     Operation::Tanh(ref x) => {
                         let tanh = x.borrow().data.tanh();
-                        x.borrow_mut().grad += self.grad * (1.0 - tanh * tanh);
+                        accumulate(x, self.grad * (1.0 - tanh * tanh));
                     }
                     Operation::ReLU(ref x) => {
                         let relu = if self.data > 0.0 { 1.0 } else { 0.0 };
-                        x.borrow_mut().grad += self.grad * relu;
+                        accumulate(x, self.grad * relu);
                     }
                     Operation::Pow(ref a, b) => {
-                        let mut inner_a = a.borrow_mut();
-                        inner_a.grad += self.grad * b * inner_a.data.powf(b - 1.0);
+                        let v = a.borrow().data;
+                        accumulate(a, self.grad * b * v.powf(b - 1.0));
                     }
                     Operation::Sub(ref a, ref b) => {
-                        a.borrow_mut().grad += self.grad;
-                        b.borrow_mut().grad -= self.grad;
+                        accumulate(a, self.grad);
+                        accumulate(b, -self.grad);
+                    }
+                    Operation::Dot(ref w, ref x) => {
+                        for (wi, xi) in w.iter().zip(x.iter()) {
+                            let (wd, xd) = (wi.borrow().data, xi.borrow().data);
+                            accumulate(wi, self.grad * xd);
+                            accumulate(xi, self.grad * wd);
+                        }
+                    }
+                    Operation::Exp(ref x) => {
+                        accumulate(x, self.grad * self.data);
+                    }
+                    Operation::Log(ref x) => {
+                        let v = x.borrow().data;
+                        accumulate(x, self.grad / v);
+                    }
+                    Operation::Abs(ref x) => {
+                        let v = x.borrow().data;
+                        // f32::signum(0.0) is 1.0, but the subgradient used at 0 is 0
+                        let sign = if v > 0.0 {
+                            1.0
+                        } else if v < 0.0 {
+                            -1.0
+                        } else {
+                            0.0
+                        };
+                        accumulate(x, self.grad * sign);
+                    }
+                    Operation::Softmax(ref xs, i, ref probs) => {
+                        // dy_i / dx_j = y_i (delta_ij - y_j)
+                        for (j, x) in xs.iter().enumerate() {
+                            let delta = if j == *i { 1.0 } else { 0.0 };
+                            accumulate(x, self.grad * probs[*i] * (delta - probs[j]));
+                        }
+                    }
+                    Operation::LogSoftmax(ref xs, i, ref probs) => {
+                        // dy_i / dx_j = delta_ij - softmax_j
+                        for (j, x) in xs.iter().enumerate() {
+                            let delta = if j == *i { 1.0 } else { 0.0 };
+                            accumulate(x, self.grad * (delta - probs[j]));
+                        }
+                    }
+                    Operation::LeakyReLU(ref x, alpha) => {
+                        let slope = if x.borrow().data > 0.0 { 1.0 } else { *alpha };
+                        accumulate(x, self.grad * slope);
+                    }
+                    Operation::Sigmoid(ref x) => {
+                        accumulate(x, self.grad * self.data * (1.0 - self.data));
+                    }
+                    Operation::GELU(ref x) => {
+                        let v = x.borrow().data;
+                        accumulate(x, self.grad * gelu_grad(v));
+                    }
+                    Operation::SiLU(ref x) => {
+                        let v = x.borrow().data;
+                        let s = sigmoid(v);
+                        accumulate(x, self.grad * s * (1.0 + v * (1.0 - s)));
+                    }
+                    Operation::ELU(ref x, alpha) => {
+                        // for x <= 0 the output is alpha * (e^x - 1), whose slope is output + alpha
+                        let slope = if x.borrow().data > 0.0 {
+                            1.0
+                        } else {
+                            self.data + alpha
+                        };
+                        accumulate(x, self.grad * slope);
                     }
                     _ => {}
                 }
@@ -0,0 +1,469 @@
+//! Saving and loading an [`MLP`] with its architecture.
+//!
+//! # Binary format, version 1
+//!
+//! All integers are unsigned 32-bit and all values are `f32`, little-endian.
+//!
+//! ```text
+//! magic        4 bytes  "MGMP"
+//! version      u32      1
+//! input        u32      number of inputs of the first layer
+//! layers       u32      number of layers
+//! per layer:
+//!   size       u32      number of neurons
+//!   activation u8       0 Identity, 1 ReLU, 2 LeakyReLU, 3 Tanh,
+//!                       4 Sigmoid, 5 GELU, 6 SiLU, 7 ELU
+//!   alpha      f32      slope of LeakyReLU / ELU, 0 otherwise
+//!   per neuron:
+//!     weights  f32 x fan-in
+//!     bias     f32
+//! ```
+//!
+//! # JSON format
+//!
+//! The same content for reading by eye:
+//!
+//! ```text
+//! {"format": "milligrad-mlp", "version": 1, "input": 2,
+//!  "layers": [{"activation": "tanh", "weights": [[0.5, -1.0]], "bias": [0.0]}]}
+//! ```
+//!
+//! LeakyReLU and ELU layers carry an extra `"alpha"`. Values are written in
+//! the shortest form that reads back to the same `f32`, so both formats
+//! restore a model bit for bit.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::json::{self, Value};
+use super::{invalid, Reader};
+use crate::nn::activation::Activation;
+use crate::nn::mlp::{Layer, Neuron, MLP};
+
+const MAGIC: &[u8; 4] = b"MGMP";
+pub const VERSION: u32 = 1;
+
+fn activation_tag(a: Activation) -> (u8, f32) {
+    match a {
+        Activation::Identity => (0, 0.0),
+        Activation::ReLU => (1, 0.0),
+        Activation::LeakyReLU(alpha) => (2, alpha),
+        Activation::Tanh => (3, 0.0),
+        Activation::Sigmoid => (4, 0.0),
+        Activation::GELU => (5, 0.0),
+        Activation::SiLU => (6, 0.0),
+        Activation::ELU(alpha) => (7, alpha),
+    }
+}
+
+fn activation_from_tag(tag: u8, alpha: f32) -> io::Result<Activation> {
+    Ok(match tag {
+        0 => Activation::Identity,
+        1 => Activation::ReLU,
+        2 => Activation::LeakyReLU(alpha),
+        3 => Activation::Tanh,
+        4 => Activation::Sigmoid,
+        5 => Activation::GELU,
+        6 => Activation::SiLU,
+        7 => Activation::ELU(alpha),
+        _ => return invalid(format!("unknown activation tag {}", tag)),
+    })
+}
+
+const ACTIVATION_NAMES: [&str; 8] = [
+    "identity",
+    "relu",
+    "leaky_relu",
+    "tanh",
+    "sigmoid",
+    "gelu",
+    "silu",
+    "elu",
+];
+
+/// The architecture and values of one layer, as stored.
+pub(super) struct LayerRecord {
+    pub activation: Activation,
+    pub weights: Vec<Vec<f32>>,
+    pub bias: Vec<f32>,
+}
+
+impl MLP {
+    /// Number of inputs the first layer takes, 0 for a model without layers.
+    pub fn input_size(&self) -> usize {
+        self.layers
+            .first()
+            .and_then(|l| l.neurons.first())
+            .map_or(0, |n| n.weights.len())
+    }
+
+    pub(super) fn records(&self) -> io::Result<Vec<LayerRecord>> {
+        self.layers
+            .iter()
+            .enumerate()
+            .map(|(i, l)| {
+                let activation = l
+                    .neurons
+                    .first()
+                    .map_or(Activation::Identity, |n| n.activation);
+                if l.neurons.iter().any(|n| n.activation != activation) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("layer {} mixes activations, which cannot be saved", i),
+                    ));
+                }
+                Ok(LayerRecord {
+                    activation,
+                    weights: l
+                        .neurons
+                        .iter()
+                        .map(|n| n.weights.iter().map(|w| w.data()).collect())
+                        .collect(),
+                    bias: l.neurons.iter().map(|n| n.bias.data()).collect(),
+                })
+            })
+            .collect()
+    }
+
+    fn from_records(input: usize, records: Vec<LayerRecord>) -> io::Result<MLP> {
+        let mut fan_in = input;
+        let mut layers = Vec::with_capacity(records.len());
+        for (i, r) in records.into_iter().enumerate() {
+            if r.weights.len() != r.bias.len() {
+                return invalid(format!(
+                    "layer {} has {} weight rows but {} biases",
+                    i,
+                    r.weights.len(),
+                    r.bias.len()
+                ));
+            }
+            if let Some(row) = r.weights.iter().find(|row| row.len() != fan_in) {
+                return invalid(format!(
+                    "layer {} expects {} inputs, but a neuron has {} weights",
+                    i,
+                    fan_in,
+                    row.len()
+                ));
+            }
+            fan_in = r.weights.len();
+            let neurons = r
+                .weights
+                .into_iter()
+                .zip(r.bias)
+                .map(|(w, b)| Neuron::from_weights(w, b, r.activation))
+                .collect();
+            layers.push(Layer { neurons });
+        }
+        Ok(MLP { layers })
+    }
+
+    /// The model in the versioned binary format described in [`crate::io::mlp`].
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let records = self.records()?;
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.input_size() as u32).to_le_bytes());
+        out.extend_from_slice(&(records.len() as u32).to_le_bytes());
+        for r in records.iter() {
+            let (tag, alpha) = activation_tag(r.activation);
+            out.extend_from_slice(&(r.bias.len() as u32).to_le_bytes());
+            out.push(tag);
+            out.extend_from_slice(&alpha.to_le_bytes());
+            for (row, b) in r.weights.iter().zip(r.bias.iter()) {
+                row.iter()
+                    .for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+                out.extend_from_slice(&b.to_le_bytes());
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<MLP> {
+        let mut r = Reader::new(bytes);
+        if r.take(4)? != MAGIC {
+            return invalid("not a milligrad MLP file (bad magic)");
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return invalid(format!(
+                "unsupported MLP format version {} (expected {})",
+                version, VERSION
+            ));
+        }
+        let input = r.u32()? as usize;
+        let n_layers = r.u32()?;
+        let mut fan_in = input;
+        let mut records = Vec::new();
+        for _ in 0..n_layers {
+            let size = r.u32()? as usize;
+            let tag = r.u8()?;
+            let activation = activation_from_tag(tag, r.f32()?)?;
+            // every neuron needs fan_in + 1 values; check before allocating
+            if r.remaining() < size.saturating_mul(fan_in + 1).saturating_mul(4) {
+                return invalid("unexpected end of data in layer weights");
+            }
+            let mut weights = Vec::with_capacity(size);
+            let mut bias = Vec::with_capacity(size);
+            for _ in 0..size {
+                weights.push(
+                    (0..fan_in)
+                        .map(|_| r.f32())
+                        .collect::<io::Result<Vec<_>>>()?,
+                );
+                bias.push(r.f32()?);
+            }
+            records.push(LayerRecord {
+                activation,
+                weights,
+                bias,
+            });
+            fan_in = size;
+        }
+        if r.remaining() != 0 {
+            return invalid(format!("{} unexpected trailing bytes", r.remaining()));
+        }
+        MLP::from_records(input, records)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes()?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<MLP> {
+        MLP::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn to_json(&self) -> io::Result<String> {
+        let records = self.records()?;
+        let mut out = String::new();
+        write!(
+            out,
+            "{{\"format\": \"milligrad-mlp\", \"version\": {}, \"input\": {}, \"layers\": [",
+            VERSION,
+            self.input_size()
+        )
+        .unwrap();
+        for (i, r) in records.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let (tag, alpha) = activation_tag(r.activation);
+            out.push_str("\n  {\"activation\": ");
+            json::write_str(&mut out, ACTIVATION_NAMES[tag as usize]);
+            if matches!(r.activation, Activation::LeakyReLU(_) | Activation::ELU(_)) {
+                out.push_str(", \"alpha\": ");
+                json::write_f32(&mut out, alpha);
+            }
+            out.push_str(",\n   \"weights\": [");
+            for (j, row) in r.weights.iter().enumerate() {
+                out.push_str(if j > 0 { ", [" } else { "[" });
+                write_values(&mut out, row);
+                out.push(']');
+            }
+            out.push_str("],\n   \"bias\": [");
+            write_values(&mut out, &r.bias);
+            out.push_str("]}");
+        }
+        out.push_str("\n]}\n");
+        Ok(out)
+    }
+
+    pub fn from_json(text: &str) -> io::Result<MLP> {
+        let v = json::parse(text).or_else(|e| invalid(format!("invalid JSON: {}", e)))?;
+        if v.get("format").and_then(Value::as_str) != Some("milligrad-mlp") {
+            return invalid("not a milligrad MLP document (missing \"format\")");
+        }
+        match v.get("version").and_then(Value::as_u64) {
+            Some(1) => {}
+            other => {
+                return invalid(format!(
+                    "unsupported MLP format version {:?} (expected {})",
+                    other, VERSION
+                ))
+            }
+        }
+        let input = field(&v, "input")?
+            .as_u64()
+            .map_or_else(|| invalid("\"input\" must be a number"), |n| Ok(n as usize))?;
+        let layers = field(&v, "layers")?
+            .as_array()
+            .map_or_else(|| invalid("\"layers\" must be an array"), Ok)?;
+        let records = layers
+            .iter()
+            .enumerate()
+            .map(|(i, l)| layer_from_json(i, l))
+            .collect::<io::Result<Vec<_>>>()?;
+        MLP::from_records(input, records)
+    }
+
+    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_json()?)
+    }
+
+    pub fn load_json<P: AsRef<Path>>(path: P) -> io::Result<MLP> {
+        MLP::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+fn write_values(out: &mut String, values: &[f32]) {
+    for (i, &v) in values.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        json::write_f32(out, v);
+    }
+}
+
+fn field<'a>(v: &'a Value, key: &str) -> io::Result<&'a Value> {
+    v.get(key)
+        .map_or_else(|| invalid(format!("missing field {:?}", key)), Ok)
+}
+
+fn values(i: usize, v: &Value) -> io::Result<Vec<f32>> {
+    v.as_array()
+        .and_then(|items| items.iter().map(Value::as_f32).collect::<Option<Vec<_>>>())
+        .map_or_else(
+            || invalid(format!("layer {}: expected an array of numbers", i)),
+            Ok,
+        )
+}
+
+fn layer_from_json(i: usize, l: &Value) -> io::Result<LayerRecord> {
+    let name = field(l, "activation")?.as_str().unwrap_or("");
+    let tag = match ACTIVATION_NAMES.iter().position(|&n| n == name) {
+        Some(tag) => tag as u8,
+        None => return invalid(format!("layer {}: unknown activation {:?}", i, name)),
+    };
+    let alpha = match l.get("alpha") {
+        Some(a) => a.as_f32().map_or_else(
+            || invalid(format!("layer {}: \"alpha\" must be a number", i)),
+            Ok,
+        )?,
+        None => 0.0,
+    };
+    let weights = field(l, "weights")?
+        .as_array()
+        .map_or_else(
+            || invalid(format!("layer {}: \"weights\" must be an array", i)),
+            Ok,
+        )?
+        .iter()
+        .map(|row| values(i, row))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(LayerRecord {
+        activation: activation_from_tag(tag, alpha)?,
+        weights,
+        bias: values(i, field(l, "bias")?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::unit::new_unit;
+    use crate::nn::Module;
+
+    fn model() -> MLP {
+        let mlp = MLP::with_activations(
+            3,
+            vec![5, 4, 2],
+            vec![
+                Activation::LeakyReLU(0.01),
+                Activation::ELU(0.7),
+                Activation::Sigmoid,
+            ],
+        );
+        // values that a decimal round trip would be likely to disturb
+        for (i, p) in mlp.parameters().iter().enumerate() {
+            p.borrow_mut().data = (i as f32 + 0.1).sqrt() / 7.0 - 0.3;
+        }
+        mlp
+    }
+
+    fn predictions(mlp: &MLP) -> Vec<u32> {
+        [[0.1, -0.7, 2.3], [1.0 / 3.0, 0.0, -5.5]]
+            .iter()
+            .flat_map(|x| mlp.eval(x.iter().map(|&v| new_unit(v)).collect()))
+            .map(|y| y.data().to_bits())
+            .collect()
+    }
+
+    fn assert_same(a: &MLP, b: &MLP) {
+        assert_eq!(a.input_size(), b.input_size());
+        assert_eq!(a.layers.len(), b.layers.len());
+        for (la, lb) in a.layers.iter().zip(b.layers.iter()) {
+            assert_eq!(la.neurons[0].activation, lb.neurons[0].activation);
+        }
+        let bits = |m: &MLP| {
+            m.parameters()
+                .iter()
+                .map(|p| p.data().to_bits())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bits(a), bits(b));
+        assert_eq!(predictions(a), predictions(b));
+    }
+
+    #[test]
+    fn test_binary_round_trip_is_bit_identical() {
+        let mlp = model();
+        let path = std::env::temp_dir().join(format!("milligrad-{}.mlp", rand::random::<u64>()));
+        mlp.save(&path).unwrap();
+        let loaded = MLP::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_same(&mlp, &loaded);
+
+        let bytes = mlp.to_bytes().unwrap();
+        assert_eq!(&bytes[..4], b"MGMP");
+        assert_eq!(bytes.len(), 16 + 3 * 9 + (5 * 4 + 4 * 6 + 2 * 5) * 4);
+    }
+
+    #[test]
+    fn test_json_round_trip_is_bit_identical() {
+        let mlp = model();
+        let path = std::env::temp_dir().join(format!("milligrad-{}.json", rand::random::<u64>()));
+        mlp.save_json(&path).unwrap();
+        let loaded = MLP::load_json(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_same(&mlp, &loaded);
+
+        let text = mlp.to_json().unwrap();
+        assert!(text.contains("\"activation\": \"leaky_relu\", \"alpha\": 0.01"));
+        assert!(text.contains("\"activation\": \"sigmoid\","));
+    }
+
+    #[test]
+    fn test_rejects_damaged_input() {
+        let bytes = model().to_bytes().unwrap();
+        let err = |b: &[u8]| MLP::from_bytes(b).unwrap_err().to_string();
+
+        assert!(err(&bytes[..bytes.len() - 1]).contains("unexpected end of data"));
+        assert!(err(&[bytes.as_slice(), &[0]].concat()).contains("1 unexpected trailing bytes"));
+        let mut wrong = bytes.clone();
+        wrong[4] = 9;
+        assert!(err(&wrong).contains("unsupported MLP format version 9"));
+        wrong[..4].copy_from_slice(b"ABCD");
+        assert!(err(&wrong).contains("bad magic"));
+        let mut tag = bytes.clone();
+        tag[20] = 42;
+        assert!(err(&tag).contains("unknown activation tag 42"));
+
+        let json = model()
+            .to_json()
+            .unwrap()
+            .replacen("\"version\": 1", "\"version\": 2", 1);
+        assert!(MLP::from_json(&json)
+            .unwrap_err()
+            .to_string()
+            .contains("version Some(2)"));
+        let json = r#"{"format": "milligrad-mlp", "version": 1, "input": 2,
+            "layers": [{"activation": "relu", "weights": [[1.0]], "bias": [0.0]}]}"#;
+        assert!(MLP::from_json(json)
+            .unwrap_err()
+            .to_string()
+            .contains("layer 0 expects 2 inputs, but a neuron has 1 weights"));
+    }
+}
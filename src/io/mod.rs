@@ -0,0 +1,58 @@
+//! Reading and writing models and arrays in on-disk formats. Malformed input
+//! is reported as an `std::io::Error` of kind `InvalidData`.
+
+use std::io;
+
+pub mod json;
+pub mod mlp;
+pub mod npy;
+pub mod onnx;
+pub mod safetensors;
+
+pub(crate) fn invalid<T, S: Into<String>>(msg: S) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, msg.into()))
+}
+
+/// Reads little-endian values from a byte slice, failing on truncation.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    pub fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        match self.pos.checked_add(n) {
+            Some(end) if end <= self.bytes.len() => {
+                let out = &self.bytes[self.pos..end];
+                self.pos = end;
+                Ok(out)
+            }
+            _ => invalid(format!(
+                "unexpected end of data: needed {} bytes at offset {}, {} left",
+                n,
+                self.pos,
+                self.bytes.len() - self.pos
+            )),
+        }
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+}
@@ -0,0 +1,717 @@
+//! NumPy's [`.npy`](https://numpy.org/doc/stable/reference/generated/numpy.lib.format.html)
+//! arrays and `.npz` archives of them:
+//!
+//! ```text
+//! magic        b"\x93NUMPY", then the format version as two bytes
+//! header size  u16 (version 1) or u32 (versions 2 and 3), little-endian
+//! header       a Python dict literal, padded with spaces and a newline:
+//!              {'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }
+//! data         the raw values
+//! ```
+//!
+//! Only little-endian floats, `<f4` and `<f8`, are supported. Arrays are
+//! returned as [`TensorData`] with their values in C (row-major) order,
+//! whatever the order of the file.
+//!
+//! An `.npz` file is a zip archive holding one `<name>.npy` per array. Arrays
+//! are written uncompressed, as `np.savez` does; archives compressed with
+//! `np.savez_compressed` are rejected. Module weights are stored one array
+//! per tensor, grouped by name as in [`safetensors`](super::safetensors), so
+//! `np.load("model.npz")["layers.0.weight"]` is the `[out, in]` weight matrix
+//! of the first layer of an `MLP`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::safetensors::{load_tensors, module_tensors, Dtype, TensorData};
+use super::{invalid, Reader};
+use crate::fundamental::unit::new_unit;
+use crate::fundamental::Unit;
+use crate::nn::state::IncompatibleKeys;
+use crate::nn::Module;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Memory layout of the values of an array with more than one dimension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    /// Row-major: the last index varies fastest.
+    C,
+    /// Column-major: the first index varies fastest.
+    Fortran,
+}
+
+/// For each position of a C-ordered array of `shape`, the position of the
+/// same element in Fortran order.
+fn fortran_positions(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for d in 1..shape.len() {
+        strides[d] = strides[d - 1] * shape[d - 1];
+    }
+    let numel = shape.iter().product::<usize>();
+    (0..numel)
+        .map(|mut i| {
+            let mut pos = 0;
+            for d in (0..shape.len()).rev() {
+                pos += (i % shape[d]) * strides[d];
+                i /= shape[d];
+            }
+            pos
+        })
+        .collect()
+}
+
+pub fn to_bytes(array: &TensorData, order: Order) -> Vec<u8> {
+    assert_eq!(
+        array.values.len(),
+        array.shape.iter().product::<usize>(),
+        "array has {} values for shape {:?}",
+        array.values.len(),
+        array.shape
+    );
+    let shape = match array.shape.len() {
+        1 => format!("({},)", array.shape[0]),
+        _ => {
+            let dims = array
+                .shape
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let descr = match array.dtype {
+        Dtype::F32 => "<f4",
+        Dtype::F64 => "<f8",
+    };
+    let fortran = if order == Order::Fortran {
+        "True"
+    } else {
+        "False"
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+        descr, fortran, shape
+    );
+    // version 1 has a 2-byte header size; version 2 is needed past that
+    let (version, size_len) = if header.len() + 12 < 1 << 16 {
+        (1, 2)
+    } else {
+        (2, 4)
+    };
+    // pad with spaces and a final newline so the data starts 64-byte aligned
+    while !(MAGIC.len() + 2 + size_len + header.len() + 1).is_multiple_of(64) {
+        header.push(' ');
+    }
+    header.push('\n');
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&[version, 0]);
+    match version {
+        1 => out.extend_from_slice(&(header.len() as u16).to_le_bytes()),
+        _ => out.extend_from_slice(&(header.len() as u32).to_le_bytes()),
+    }
+    out.extend_from_slice(header.as_bytes());
+    let mut values = array.values.clone();
+    if order == Order::Fortran {
+        for (v, pos) in array.values.iter().zip(fortran_positions(&array.shape)) {
+            values[pos] = *v;
+        }
+    }
+    for v in values {
+        match array.dtype {
+            Dtype::F32 => out.extend_from_slice(&(v as f32).to_le_bytes()),
+            Dtype::F64 => out.extend_from_slice(&v.to_le_bytes()),
+        }
+    }
+    out
+}
+
+/// A value of the Python literal in an `.npy` header.
+#[derive(Debug, PartialEq)]
+enum Literal {
+    Str(String),
+    Bool(bool),
+    Tuple(Vec<usize>),
+}
+
+/// Parses the header dict, e.g. `{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }`.
+fn parse_header(text: &str) -> io::Result<BTreeMap<String, Literal>> {
+    let mut chars = text.trim().chars().peekable();
+    let fail = |what: &str| invalid(format!("invalid .npy header {:?}: {}", text, what));
+
+    fn skip_spaces(chars: &mut std::iter::Peekable<std::str::Chars>) {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+    fn string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+        let quote = chars.next().filter(|&c| c == '\'' || c == '"')?;
+        let mut s = String::new();
+        loop {
+            match chars.next()? {
+                c if c == quote => return Some(s),
+                c => s.push(c),
+            }
+        }
+    }
+
+    if chars.next() != Some('{') {
+        return fail("expected a dict");
+    }
+    let mut dict = BTreeMap::new();
+    loop {
+        skip_spaces(&mut chars);
+        if chars.peek() == Some(&'}') {
+            chars.next();
+            break;
+        }
+        let key = match string(&mut chars) {
+            Some(key) => key,
+            None => return fail("expected a string key"),
+        };
+        skip_spaces(&mut chars);
+        if chars.next() != Some(':') {
+            return fail("expected ':' after a key");
+        }
+        skip_spaces(&mut chars);
+        let value = match chars.peek() {
+            Some('\'') | Some('"') => match string(&mut chars) {
+                Some(s) => Literal::Str(s),
+                None => return fail("unterminated string"),
+            },
+            Some('(') => {
+                chars.next();
+                let mut body = String::new();
+                loop {
+                    match chars.next() {
+                        Some(')') => break,
+                        Some(c) => body.push(c),
+                        None => return fail("unterminated tuple"),
+                    }
+                }
+                let mut dims = Vec::new();
+                for item in body.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                    // Python 2 wrote long integers with an `L` suffix
+                    match item.trim_end_matches('L').parse() {
+                        Ok(d) => dims.push(d),
+                        Err(_) => return fail("shape must be a tuple of integers"),
+                    }
+                }
+                Literal::Tuple(dims)
+            }
+            _ => {
+                let word = std::iter::from_fn(|| chars.next_if(|c| c.is_alphanumeric()))
+                    .collect::<String>();
+                match word.as_str() {
+                    "True" => Literal::Bool(true),
+                    "False" => Literal::Bool(false),
+                    _ => return fail("expected a string, a tuple or a bool"),
+                }
+            }
+        };
+        dict.insert(key, value);
+        skip_spaces(&mut chars);
+        match chars.next() {
+            Some(',') => {}
+            Some('}') => break,
+            _ => return fail("expected ',' or '}'"),
+        }
+    }
+    skip_spaces(&mut chars);
+    if chars.next().is_some() {
+        return fail("trailing characters after the dict");
+    }
+    Ok(dict)
+}
+
+/// Parses an `.npy` buffer. The values are returned in C order.
+pub fn from_bytes(bytes: &[u8]) -> io::Result<TensorData> {
+    let mut r = Reader::new(bytes);
+    if bytes.len() < MAGIC.len() || r.take(MAGIC.len())? != MAGIC {
+        return invalid("not an .npy file: bad magic");
+    }
+    let (major, minor) = (r.u8()?, r.u8()?);
+    let size = match major {
+        1 => u16::from_le_bytes(r.take(2)?.try_into().unwrap()) as usize,
+        2 | 3 => r.u32()? as usize,
+        _ => return invalid(format!("unsupported .npy version {}.{}", major, minor)),
+    };
+    let header =
+        std::str::from_utf8(r.take(size)?).or_else(|_| invalid(".npy header is not UTF-8"))?;
+    let dict = parse_header(header)?;
+
+    let dtype = match dict.get("descr") {
+        Some(Literal::Str(d)) if d == "<f4" => Dtype::F32,
+        Some(Literal::Str(d)) if d == "<f8" => Dtype::F64,
+        Some(Literal::Str(d)) => {
+            return invalid(format!(
+                "unsupported dtype {:?}; only '<f4' and '<f8' can be read",
+                d
+            ))
+        }
+        _ => return invalid(".npy header has no string 'descr'"),
+    };
+    let fortran = match dict.get("fortran_order") {
+        Some(Literal::Bool(b)) => *b,
+        _ => return invalid(".npy header has no bool 'fortran_order'"),
+    };
+    let shape = match dict.get("shape") {
+        Some(Literal::Tuple(dims)) => dims.clone(),
+        _ => return invalid(".npy header has no tuple 'shape'"),
+    };
+
+    let numel = shape
+        .iter()
+        .try_fold(1usize, |acc, &d| acc.checked_mul(d))
+        .and_then(|n| n.checked_mul(dtype.size()));
+    if numel != Some(r.remaining()) {
+        return invalid(format!(
+            "array of shape {:?} needs {:?} bytes of data, found {}",
+            shape,
+            numel,
+            r.remaining()
+        ));
+    }
+    let raw = r.take(r.remaining())?;
+    let stored: Vec<f64> = match dtype {
+        Dtype::F32 => raw
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+            .collect(),
+        Dtype::F64 => raw
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+    };
+    let values = if fortran {
+        fortran_positions(&shape)
+            .into_iter()
+            .map(|pos| stored[pos])
+            .collect()
+    } else {
+        stored
+    };
+    Ok(TensorData {
+        dtype,
+        shape,
+        values,
+    })
+}
+
+pub fn save<P: AsRef<Path>>(path: P, array: &TensorData, order: Order) -> io::Result<()> {
+    fs::write(path, to_bytes(array, order))
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<TensorData> {
+    from_bytes(&fs::read(path)?)
+}
+
+/// The rows of a dataset as inputs for `MLP::eval`: each row of a 2-D array,
+/// or each element of a 1-D array as a one-feature row.
+pub fn to_rows(array: &TensorData) -> io::Result<Vec<Vec<Unit>>> {
+    let width = match array.shape.len() {
+        1 => 1,
+        2 => array.shape[1],
+        _ => {
+            return invalid(format!(
+                "a dataset must be a 1-D or 2-D array, found shape {:?}",
+                array.shape
+            ))
+        }
+    };
+    if width == 0 {
+        return Ok(vec![vec![]; array.shape[0]]);
+    }
+    Ok(array
+        .values
+        .chunks(width)
+        .map(|row| row.iter().map(|&v| new_unit(v as f32)).collect())
+        .collect())
+}
+
+/// A 2-D array of the values of `rows`, e.g. the predictions of a model.
+pub fn from_rows(rows: &[Vec<Unit>], dtype: Dtype) -> TensorData {
+    let width = rows.first().map_or(0, |r| r.len());
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(
+            row.len(),
+            width,
+            "row {} has {} values, but the first row has {}",
+            i,
+            row.len(),
+            width
+        );
+    }
+    TensorData {
+        dtype,
+        shape: vec![rows.len(), width],
+        values: rows.iter().flatten().map(|u| u.data() as f64).collect(),
+    }
+}
+
+/// CRC-32 as used by zip (reflected, polynomial 0xEDB88320).
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+/// An `.npz` archive of `arrays`, each stored uncompressed in C order.
+pub fn npz_to_bytes(arrays: &BTreeMap<String, TensorData>) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for (name, array) in arrays.iter() {
+        let file_name = format!("{}.npy", name);
+        let data = to_bytes(array, Order::C);
+        let (crc, size) = (crc32(&data), data.len() as u32);
+        let offset = out.len() as u32;
+
+        // version needed, flags, method (stored), time, date
+        let common = |v: &mut Vec<u8>| {
+            v.extend_from_slice(&20u16.to_le_bytes());
+            v.extend_from_slice(&[0; 4]);
+            v.extend_from_slice(&[0, 0, 0x21, 0]); // 1980-01-01 00:00
+            v.extend_from_slice(&crc.to_le_bytes());
+            v.extend_from_slice(&size.to_le_bytes());
+            v.extend_from_slice(&size.to_le_bytes());
+            v.extend_from_slice(&(file_name.len() as u16).to_le_bytes());
+            v.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        };
+        out.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        common(&mut out);
+        out.extend_from_slice(file_name.as_bytes());
+        out.extend_from_slice(&data);
+
+        central.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        common(&mut central);
+        // comment length, disk number, internal and external attributes
+        central.extend_from_slice(&[0; 10]);
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(file_name.as_bytes());
+    }
+    let (offset, count) = (out.len() as u32, arrays.len() as u16);
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); // disk numbers
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    out
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> io::Result<u16> {
+    let mut r = Reader::new(bytes);
+    r.take(at)?;
+    Ok(u16::from_le_bytes(r.take(2)?.try_into().unwrap()))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> io::Result<u32> {
+    let mut r = Reader::new(bytes);
+    r.take(at)?;
+    r.u32()
+}
+
+/// Reads the size, compressed size and offset of an entry from its zip64
+/// extra field; only the fields saturated in the central header are present.
+fn zip64_fields(extra: &[u8], mut fields: [u64; 3]) -> io::Result<[u64; 3]> {
+    let mut r = Reader::new(extra);
+    while r.remaining() >= 4 {
+        let id = u16::from_le_bytes(r.take(2)?.try_into().unwrap());
+        let len = u16::from_le_bytes(r.take(2)?.try_into().unwrap()) as usize;
+        let mut data = Reader::new(r.take(len)?);
+        if id == 1 {
+            for f in fields.iter_mut().filter(|f| **f == u32::MAX as u64) {
+                *f = u64::from_le_bytes(data.take(8)?.try_into().unwrap());
+            }
+            return Ok(fields);
+        }
+    }
+    invalid("zip entry has saturated sizes but no zip64 extra field")
+}
+
+/// Parses an `.npz` archive into arrays keyed by name, without the `.npy`
+/// extension.
+pub fn npz_from_bytes(bytes: &[u8]) -> io::Result<BTreeMap<String, TensorData>> {
+    // the end record is at least 22 bytes and followed by a comment of up to 64 KiB
+    if bytes.len() < 22 {
+        return invalid("not an .npz file: too short for a zip end of central directory");
+    }
+    let eocd = (0..=bytes.len().saturating_sub(22))
+        .rev()
+        .take(22 + u16::MAX as usize)
+        .find(|&i| bytes[i..i + 4] == END_OF_CENTRAL_DIRECTORY.to_le_bytes())
+        .map_or_else(
+            || invalid("not an .npz file: no zip end of central directory"),
+            Ok,
+        )?;
+    let count = u16_at(bytes, eocd + 10)? as usize;
+    let mut pos = u32_at(bytes, eocd + 16)? as usize;
+    if pos == u32::MAX as usize {
+        return invalid("zip64 archives are not supported");
+    }
+
+    let mut arrays = BTreeMap::new();
+    for _ in 0..count {
+        if u32_at(bytes, pos)? != CENTRAL_HEADER {
+            return invalid(format!("bad zip central directory entry at byte {}", pos));
+        }
+        let method = u16_at(bytes, pos + 10)?;
+        let crc = u32_at(bytes, pos + 16)?;
+        let name_len = u16_at(bytes, pos + 28)? as usize;
+        let extra_len = u16_at(bytes, pos + 30)? as usize;
+        let comment_len = u16_at(bytes, pos + 32)? as usize;
+        let mut r = Reader::new(bytes);
+        r.take(pos + 46)?;
+        let name = std::str::from_utf8(r.take(name_len)?)
+            .or_else(|_| invalid("zip entry name is not UTF-8"))?
+            .to_string();
+        let extra = r.take(extra_len)?;
+        let mut fields = [
+            u32_at(bytes, pos + 24)? as u64,
+            u32_at(bytes, pos + 20)? as u64,
+            u32_at(bytes, pos + 42)? as u64,
+        ];
+        if fields.contains(&(u32::MAX as u64)) {
+            fields = zip64_fields(extra, fields)?;
+        }
+        let [size, compressed, offset] = fields;
+        pos += 46 + name_len + extra_len + comment_len;
+
+        if method != 0 {
+            return invalid(format!(
+                "{} is compressed (method {}); only uncompressed archives, as written by \
+                 np.savez, are supported",
+                name, method
+            ));
+        }
+        if size != compressed {
+            return invalid(format!("stored zip entry {} changes size", name));
+        }
+        let offset = offset as usize;
+        if u32_at(bytes, offset)? != LOCAL_HEADER {
+            return invalid(format!("bad zip local header for {}", name));
+        }
+        // the local header repeats the name and may have its own extra field
+        let skip = 30 + u16_at(bytes, offset + 26)? as usize + u16_at(bytes, offset + 28)? as usize;
+        let mut r = Reader::new(bytes);
+        r.take(offset + skip)?;
+        let data = r.take(size as usize)?;
+        if crc32(data) != crc {
+            return invalid(format!("CRC mismatch for {}", name));
+        }
+        let key = name.strip_suffix(".npy").unwrap_or(&name).to_string();
+        let array = from_bytes(data).or_else(|e| invalid(format!("in {}: {}", name, e)))?;
+        if arrays.insert(key, array).is_some() {
+            return invalid(format!("{} appears twice in the archive", name));
+        }
+    }
+    Ok(arrays)
+}
+
+pub fn save_npz<P: AsRef<Path>>(path: P, arrays: &BTreeMap<String, TensorData>) -> io::Result<()> {
+    fs::write(path, npz_to_bytes(arrays))
+}
+
+pub fn load_npz<P: AsRef<Path>>(path: P) -> io::Result<BTreeMap<String, TensorData>> {
+    npz_from_bytes(&fs::read(path)?)
+}
+
+/// The parameters of `module` as an `.npz` archive, one array per tensor.
+pub fn module_to_npz<M: Module + ?Sized>(module: &M, dtype: Dtype) -> io::Result<Vec<u8>> {
+    Ok(npz_to_bytes(&module_tensors(module, dtype)?))
+}
+
+/// Loads the arrays of an `.npz` archive into the parameters of `module`,
+/// with the same checks as `safetensors::load_module_from_bytes`.
+pub fn load_module_from_npz<M: Module + ?Sized>(
+    module: &mut M,
+    bytes: &[u8],
+    strict: bool,
+) -> io::Result<IncompatibleKeys> {
+    load_tensors(module, &npz_from_bytes(bytes)?, strict)
+}
+
+pub fn save_module_npz<M: Module + ?Sized, P: AsRef<Path>>(
+    module: &M,
+    path: P,
+    dtype: Dtype,
+) -> io::Result<()> {
+    fs::write(path, module_to_npz(module, dtype)?)
+}
+
+pub fn load_module_npz<M: Module + ?Sized, P: AsRef<Path>>(
+    module: &mut M,
+    path: P,
+    strict: bool,
+) -> io::Result<IncompatibleKeys> {
+    load_module_from_npz(module, &fs::read(path)?, strict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::mlp::MLP;
+
+    fn array(dtype: Dtype, shape: Vec<usize>, values: Vec<f64>) -> TensorData {
+        TensorData {
+            dtype,
+            shape,
+            values,
+        }
+    }
+
+    /// What `np.save` writes for `np.arange(6, dtype="<f4").reshape(2, 3)`.
+    fn numpy_file(fortran: bool, values: &[f32]) -> Vec<u8> {
+        let dict = format!(
+            "{{'descr': '<f4', 'fortran_order': {}, 'shape': (2, 3), }}",
+            if fortran { "True" } else { "False" }
+        );
+        let mut out = b"\x93NUMPY\x01\x00".to_vec();
+        let header = format!("{:<117}\n", dict);
+        out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        out.extend_from_slice(header.as_bytes());
+        for v in values {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_reads_numpy_layout_in_both_orders() {
+        let expected = array(Dtype::F32, vec![2, 3], vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        let c = numpy_file(false, &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(from_bytes(&c).unwrap(), expected);
+        // column-major: the first index varies fastest
+        let f = numpy_file(true, &[0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+        assert_eq!(from_bytes(&f).unwrap(), expected);
+
+        // and writes the same bytes numpy does
+        assert_eq!(to_bytes(&expected, Order::C), c);
+        assert_eq!(to_bytes(&expected, Order::Fortran), f);
+        assert_eq!(c.len() - 6 * 4, 128);
+    }
+
+    #[test]
+    fn test_round_trip_shapes_and_dtypes() {
+        let cases = [
+            array(Dtype::F64, vec![], vec![2.5]),
+            array(Dtype::F64, vec![3], vec![1.0, -2.0, 1e-300]),
+            array(Dtype::F32, vec![0, 4], vec![]),
+            array(Dtype::F32, vec![2, 3, 2], (0..12).map(f64::from).collect()),
+        ];
+        for a in cases.iter() {
+            for order in [Order::C, Order::Fortran] {
+                let bytes = to_bytes(a, order);
+                assert_eq!(bytes.len() - a.values.len() * a.dtype.size(), 128);
+                assert_eq!(from_bytes(&bytes).unwrap(), *a, "{:?}", order);
+            }
+        }
+        let header = parse_header("{'descr': '<f8', 'fortran_order': False, 'shape': (), }");
+        assert_eq!(header.unwrap()["shape"], Literal::Tuple(vec![]));
+    }
+
+    #[test]
+    fn test_rejects_unsupported_and_truncated_files() {
+        let err = |b: &[u8]| from_bytes(b).unwrap_err().to_string();
+        let mut big_endian = numpy_file(false, &[0.0; 6]);
+        big_endian[21] = b'>';
+        assert!(err(&big_endian).contains("unsupported dtype \">f4\""));
+        let c = numpy_file(false, &[0.0; 6]);
+        assert!(err(&c[..c.len() - 1]).contains("needs Some(24) bytes of data, found 23"));
+        assert!(err(b"PK\x03\x04").contains("bad magic"));
+        assert!(parse_header("{'descr': '<f4' 'shape': (2,)}").is_err());
+        assert!(parse_header("{'shape': (2, x)}").is_err());
+    }
+
+    #[test]
+    fn test_npz_round_trip_and_corruption() {
+        let mut arrays = BTreeMap::new();
+        arrays.insert(
+            "x".to_string(),
+            array(Dtype::F32, vec![2, 2], vec![1.0, 2.0, 3.0, 4.0]),
+        );
+        arrays.insert("y".to_string(), array(Dtype::F64, vec![2], vec![0.5, -0.5]));
+        let bytes = npz_to_bytes(&arrays);
+        assert_eq!(&bytes[..4], b"PK\x03\x04");
+        assert_eq!(&bytes[30..35], b"x.npy");
+        // DOS date 0x0021 is 1980-01-01
+        assert_eq!(&bytes[12..14], &[0x21, 0]);
+        assert_eq!(npz_from_bytes(&bytes).unwrap(), arrays);
+
+        let mut corrupt = bytes.clone();
+        corrupt[30 + 5 + 130] ^= 1;
+        assert!(npz_from_bytes(&corrupt)
+            .unwrap_err()
+            .to_string()
+            .contains("CRC mismatch for x.npy"));
+        let mut deflated = bytes.clone();
+        deflated[8] = 8;
+        let central = bytes.len() - 22 - 2 * (46 + 5);
+        deflated[central + 10] = 8;
+        assert!(npz_from_bytes(&deflated)
+            .unwrap_err()
+            .to_string()
+            .contains("x.npy is compressed (method 8)"));
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        for truncated in [&[][..], &bytes[..3], &bytes[..21]] {
+            assert!(npz_from_bytes(truncated)
+                .unwrap_err()
+                .to_string()
+                .contains("not an .npz file"));
+        }
+        assert!(npz_from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_dataset_and_weights() {
+        let mut a = MLP::new(3, vec![4], 2);
+        let dataset = array(Dtype::F32, vec![2, 3], vec![0.1, 0.2, 0.3, -1.0, 0.0, 1.0]);
+        let rows = to_rows(&from_bytes(&to_bytes(&dataset, Order::Fortran)).unwrap()).unwrap();
+        let predictions = rows.into_iter().map(|x| a.eval(x)).collect::<Vec<_>>();
+        let out = from_rows(&predictions, Dtype::F64);
+        assert_eq!(out.shape, vec![2, 2]);
+        assert_eq!(out.values[3], predictions[1][1].data() as f64);
+
+        let path = std::env::temp_dir().join(format!("milligrad-{}.npz", rand::random::<u64>()));
+        save_module_npz(&a, &path, Dtype::F32).unwrap();
+        let arrays = load_npz(&path).unwrap();
+        assert_eq!(arrays["layers.0.weight"].shape, vec![4, 3]);
+        assert_eq!(arrays["layers.1.bias"].shape, vec![2]);
+
+        let b = MLP::new(3, vec![4], 2);
+        let bytes = module_to_npz(&b, Dtype::F32).unwrap();
+        let keys = load_module_from_npz(&mut a, &bytes, true).unwrap();
+        assert!(keys.is_empty());
+        let bits = |m: &MLP| {
+            m.parameters()
+                .iter()
+                .map(|p| p.data().to_bits())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bits(&a), bits(&b));
+        let mut c = MLP::new(3, vec![4], 2);
+        load_module_npz(&mut c, &path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_ne!(bits(&c), bits(&b));
+    }
+}
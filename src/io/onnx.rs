@@ -0,0 +1,440 @@
+//! Export of an [`MLP`] as an ONNX model (opset 13).
+//!
+//! Every layer becomes a `Gemm` node computing `x W^T + b`, with its weights
+//! (`layers.<i>.weight`, shape `[out, in]`) and bias (`layers.<i>.bias`) as
+//! initializers, followed by the nodes of its activation. The graph takes
+//! `input` of shape `[batch, in]` and produces `output` of shape
+//! `[batch, out]`. GELU and SiLU have no operator in opset 13 and are built
+//! from elementary nodes, GELU with the same tanh approximation as
+//! `op::gelu`.
+//!
+//! Only the part of the ONNX protobuf schema needed here is encoded.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::nn::activation::Activation;
+use crate::nn::mlp::MLP;
+
+pub const IR_VERSION: u64 = 7;
+pub const OPSET: u64 = 13;
+
+const FLOAT: u64 = 1;
+const ATTRIBUTE_FLOAT: u64 = 1;
+const ATTRIBUTE_INT: u64 = 2;
+
+/// A protobuf message under construction.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.0.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        self.0.push(v as u8);
+    }
+
+    fn key(&mut self, field: u64, wire_type: u64) {
+        self.varint(field << 3 | wire_type);
+    }
+
+    fn uint(&mut self, field: u64, v: u64) -> &mut Self {
+        self.key(field, 0);
+        self.varint(v);
+        self
+    }
+
+    fn float(&mut self, field: u64, v: f32) -> &mut Self {
+        self.key(field, 5);
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn bytes(&mut self, field: u64, b: &[u8]) -> &mut Self {
+        self.key(field, 2);
+        self.varint(b.len() as u64);
+        self.0.extend_from_slice(b);
+        self
+    }
+
+    fn string(&mut self, field: u64, s: &str) -> &mut Self {
+        self.bytes(field, s.as_bytes())
+    }
+
+    fn message(&mut self, field: u64, m: &Message) -> &mut Self {
+        self.bytes(field, &m.0)
+    }
+}
+
+enum Attribute {
+    Float(&'static str, f32),
+    Int(&'static str, u64),
+}
+
+fn node(
+    op_type: &str,
+    name: &str,
+    inputs: &[&str],
+    output: &str,
+    attributes: &[Attribute],
+) -> Message {
+    let mut m = Message::default();
+    for i in inputs {
+        m.string(1, i);
+    }
+    m.string(2, output).string(3, name).string(4, op_type);
+    for a in attributes {
+        let mut attr = Message::default();
+        match *a {
+            Attribute::Float(n, v) => attr.string(1, n).float(2, v).uint(20, ATTRIBUTE_FLOAT),
+            Attribute::Int(n, v) => attr.string(1, n).uint(3, v).uint(20, ATTRIBUTE_INT),
+        };
+        m.message(5, &attr);
+    }
+    m
+}
+
+fn tensor(name: &str, dims: &[usize], values: &[f32]) -> Message {
+    let mut m = Message::default();
+    for &d in dims {
+        m.uint(1, d as u64);
+    }
+    m.uint(2, FLOAT).string(8, name);
+    let raw = values
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect::<Vec<_>>();
+    m.bytes(9, &raw);
+    m
+}
+
+/// A float tensor of shape `[batch, size]`.
+fn value_info(name: &str, size: usize) -> Message {
+    let mut batch = Message::default();
+    batch.string(2, "batch");
+    let mut features = Message::default();
+    features.uint(1, size as u64);
+    let mut shape = Message::default();
+    shape.message(1, &batch).message(1, &features);
+    let mut tensor_type = Message::default();
+    tensor_type.uint(1, FLOAT).message(2, &shape);
+    let mut type_proto = Message::default();
+    type_proto.message(1, &tensor_type);
+    let mut m = Message::default();
+    m.string(1, name).message(2, &type_proto);
+    m
+}
+
+/// Nodes and initializers of a graph being assembled.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Message>,
+    initializers: Vec<Message>,
+    constants: Vec<&'static str>,
+}
+
+impl Graph {
+    fn push(
+        &mut self,
+        op_type: &str,
+        name: String,
+        inputs: &[&str],
+        attributes: &[Attribute],
+    ) -> String {
+        self.nodes
+            .push(node(op_type, &name, inputs, &name, attributes));
+        name
+    }
+
+    /// A scalar initializer, added once however often it is used.
+    fn constant(&mut self, name: &'static str, value: f32) -> &'static str {
+        if !self.constants.contains(&name) {
+            self.constants.push(name);
+            self.initializers.push(tensor(name, &[], &[value]));
+        }
+        name
+    }
+
+    /// Nodes applying `activation` to `x`, returning the name of the result.
+    fn activation(&mut self, activation: Activation, x: String, prefix: &str) -> String {
+        let name = |op: &str| format!("{}.{}", prefix, op);
+        match activation {
+            Activation::Identity => x,
+            Activation::ReLU => self.push("Relu", name("relu"), &[&x], &[]),
+            Activation::LeakyReLU(alpha) => self.push(
+                "LeakyRelu",
+                name("leaky_relu"),
+                &[&x],
+                &[Attribute::Float("alpha", alpha)],
+            ),
+            Activation::Tanh => self.push("Tanh", name("tanh"), &[&x], &[]),
+            Activation::Sigmoid => self.push("Sigmoid", name("sigmoid"), &[&x], &[]),
+            Activation::ELU(alpha) => self.push(
+                "Elu",
+                name("elu"),
+                &[&x],
+                &[Attribute::Float("alpha", alpha)],
+            ),
+            Activation::SiLU => {
+                let s = self.push("Sigmoid", name("sigmoid"), &[&x], &[]);
+                self.push("Mul", name("silu"), &[&x, &s], &[])
+            }
+            Activation::GELU => {
+                // 0.5 x (1 + tanh(sqrt(2 / pi) (x + 0.044715 x^3)))
+                let (c, k) = (
+                    self.constant("gelu_c", 0.797_884_6),
+                    self.constant("gelu_k", 0.044715),
+                );
+                let (half, one) = (self.constant("half", 0.5), self.constant("one", 1.0));
+                let x2 = self.push("Mul", name("x2"), &[&x, &x], &[]);
+                let x3 = self.push("Mul", name("x3"), &[&x2, &x], &[]);
+                let kx3 = self.push("Mul", name("kx3"), &[&x3, k], &[]);
+                let inner = self.push("Add", name("inner"), &[&x, &kx3], &[]);
+                let scaled = self.push("Mul", name("scaled"), &[&inner, c], &[]);
+                let t = self.push("Tanh", name("tanh"), &[&scaled], &[]);
+                let t1 = self.push("Add", name("tanh_plus_one"), &[&t, one], &[]);
+                let hx = self.push("Mul", name("half_x"), &[&x, half], &[]);
+                self.push("Mul", name("gelu"), &[&hx, &t1], &[])
+            }
+        }
+    }
+}
+
+/// The model as the bytes of an ONNX `ModelProto`.
+pub fn export_mlp(mlp: &MLP) -> io::Result<Vec<u8>> {
+    let records = mlp.records()?;
+    let mut g = Graph::default();
+    let mut x = "input".to_string();
+    for (i, r) in records.iter().enumerate() {
+        let prefix = format!("layers.{}", i);
+        let (w, b) = (format!("{}.weight", prefix), format!("{}.bias", prefix));
+        let fan_in = r.weights.first().map_or(0, |row| row.len());
+        g.initializers
+            .push(tensor(&w, &[r.weights.len(), fan_in], &r.weights.concat()));
+        g.initializers.push(tensor(&b, &[r.bias.len()], &r.bias));
+        let y = g.push(
+            "Gemm",
+            format!("{}.gemm", prefix),
+            &[&x, &w, &b],
+            &[Attribute::Int("transB", 1)],
+        );
+        x = g.activation(r.activation, y, &prefix);
+    }
+    let output_size = records.last().map_or(mlp.input_size(), |r| r.bias.len());
+    // name the last value `output`
+    g.nodes
+        .push(node("Identity", "output", &[&x], "output", &[]));
+
+    let mut graph = Message::default();
+    for n in g.nodes.iter() {
+        graph.message(1, n);
+    }
+    graph.string(2, "mlp");
+    for t in g.initializers.iter() {
+        graph.message(5, t);
+    }
+    graph
+        .message(11, &value_info("input", mlp.input_size()))
+        .message(12, &value_info("output", output_size));
+
+    let mut opset = Message::default();
+    opset.string(1, "").uint(2, OPSET);
+    let mut model = Message::default();
+    model
+        .uint(1, IR_VERSION)
+        .string(2, "milligrad")
+        .string(3, env!("CARGO_PKG_VERSION"))
+        .message(7, &graph)
+        .message(8, &opset);
+    Ok(model.0)
+}
+
+impl MLP {
+    pub fn save_onnx<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, export_mlp(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A decoded protobuf field: varints, fixed32 values and length-delimited bytes.
+    #[derive(Debug, Clone)]
+    enum Field {
+        Varint(u64),
+        Fixed32(u32),
+        Bytes(Vec<u8>),
+    }
+
+    fn decode(bytes: &[u8]) -> Vec<(u64, Field)> {
+        let mut pos = 0;
+        let varint = |pos: &mut usize| {
+            let (mut v, mut shift) = (0u64, 0);
+            loop {
+                let b = bytes[*pos];
+                *pos += 1;
+                v |= ((b & 0x7f) as u64) << shift;
+                shift += 7;
+                if b < 0x80 {
+                    return v;
+                }
+            }
+        };
+        let mut fields = Vec::new();
+        while pos < bytes.len() {
+            let key = varint(&mut pos);
+            let field = match key & 7 {
+                0 => Field::Varint(varint(&mut pos)),
+                5 => {
+                    let v = u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap());
+                    pos += 4;
+                    Field::Fixed32(v)
+                }
+                2 => {
+                    let n = varint(&mut pos) as usize;
+                    pos += n;
+                    Field::Bytes(bytes[pos - n..pos].to_vec())
+                }
+                t => panic!("unexpected wire type {}", t),
+            };
+            fields.push((key >> 3, field));
+        }
+        assert_eq!(pos, bytes.len());
+        fields
+    }
+
+    fn all(fields: &[(u64, Field)], number: u64) -> Vec<Field> {
+        fields
+            .iter()
+            .filter(|(n, _)| *n == number)
+            .map(|(_, f)| f.clone())
+            .collect()
+    }
+
+    fn bytes(f: &Field) -> Vec<u8> {
+        match f {
+            Field::Bytes(b) => b.clone(),
+            other => panic!("expected bytes, got {:?}", other),
+        }
+    }
+
+    fn text(f: &Field) -> String {
+        String::from_utf8(bytes(f)).unwrap()
+    }
+
+    fn message(fields: &[(u64, Field)], number: u64) -> Vec<(u64, Field)> {
+        decode(&bytes(&all(fields, number)[0]))
+    }
+
+    fn varint(fields: &[(u64, Field)], number: u64) -> u64 {
+        match all(fields, number)[0] {
+            Field::Varint(v) => v,
+            ref other => panic!("expected a varint, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_export_graph_structure() {
+        let mlp = MLP::with_activations(
+            3,
+            vec![4, 2],
+            vec![Activation::LeakyReLU(0.2), Activation::Sigmoid],
+        );
+        let model = decode(&export_mlp(&mlp).unwrap());
+        assert_eq!(varint(&model, 1), IR_VERSION);
+        assert_eq!(text(&all(&model, 2)[0]), "milligrad");
+        assert_eq!(varint(&message(&model, 8), 2), OPSET);
+
+        let graph = message(&model, 7);
+        let nodes = all(&graph, 1)
+            .iter()
+            .map(|n| decode(&bytes(n)))
+            .collect::<Vec<_>>();
+        let ops = nodes
+            .iter()
+            .map(|n| text(&all(n, 4)[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops,
+            vec!["Gemm", "LeakyRelu", "Gemm", "Sigmoid", "Identity"]
+        );
+
+        // each node reads the previous one's output
+        let inputs = |n: &[(u64, Field)]| all(n, 1).iter().map(text).collect::<Vec<_>>();
+        let output = |n: &[(u64, Field)]| text(&all(n, 2)[0]);
+        assert_eq!(
+            inputs(&nodes[0]),
+            vec!["input", "layers.0.weight", "layers.0.bias"]
+        );
+        assert_eq!(inputs(&nodes[1]), vec![output(&nodes[0])]);
+        assert_eq!(inputs(&nodes[2])[0], output(&nodes[1]));
+        assert_eq!(output(&nodes[4]), "output");
+
+        let trans_b = decode(&bytes(&all(&nodes[0], 5)[0]));
+        assert_eq!(text(&all(&trans_b, 1)[0]), "transB");
+        assert_eq!(varint(&trans_b, 3), 1);
+        let alpha = decode(&bytes(&all(&nodes[1], 5)[0]));
+        match all(&alpha, 2)[0] {
+            Field::Fixed32(bits) => assert_eq!(f32::from_bits(bits), 0.2),
+            ref other => panic!("expected a float, got {:?}", other),
+        }
+
+        let initializers = all(&graph, 5)
+            .iter()
+            .map(|t| decode(&bytes(t)))
+            .collect::<Vec<_>>();
+        assert_eq!(initializers.len(), 4);
+        let w = &initializers[0];
+        assert_eq!(text(&all(w, 8)[0]), "layers.0.weight");
+        let dims = all(w, 1)
+            .iter()
+            .map(|d| match d {
+                Field::Varint(v) => *v,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(dims, vec![4, 3]);
+        let raw = bytes(&all(w, 9)[0]);
+        let first_row = mlp.layers[0].neurons[0]
+            .weights
+            .iter()
+            .flat_map(|u| u.data().to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(&raw[..12], &first_row[..]);
+
+        // input is [batch, 3]
+        let input = message(&graph, 11);
+        assert_eq!(text(&all(&input, 1)[0]), "input");
+        let tensor_type = message(&message(&input, 2), 1);
+        let shape = message(&tensor_type, 2);
+        let dims = all(&shape, 1)
+            .iter()
+            .map(|d| decode(&bytes(d)))
+            .collect::<Vec<_>>();
+        assert_eq!(text(&all(&dims[0], 2)[0]), "batch");
+        assert_eq!(varint(&dims[1], 1), 3);
+    }
+
+    #[test]
+    fn test_gelu_is_decomposed_with_shared_constants() {
+        let mlp = MLP::with_activations(2, vec![2, 2], vec![Activation::GELU, Activation::GELU]);
+        let graph = message(&decode(&export_mlp(&mlp).unwrap()), 7);
+        let ops = all(&graph, 1)
+            .iter()
+            .map(|n| text(&all(&decode(&bytes(n)), 4)[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(ops.iter().filter(|op| *op == "Gemm").count(), 2);
+        assert_eq!(ops.iter().filter(|op| *op == "Tanh").count(), 2);
+        // four weights and biases plus four scalar constants
+        assert_eq!(all(&graph, 5).len(), 4 + 4);
+
+        // the decomposition evaluates to the same values as op::gelu
+        let x = 0.7f32;
+        let decomposed = 0.5 * x * (1.0 + (0.797_884_6 * (x + 0.044715 * x * x * x)).tanh());
+        assert_eq!(decomposed, crate::fundamental::unit::gelu(x));
+    }
+}
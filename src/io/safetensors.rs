@@ -0,0 +1,620 @@
+//! The [safetensors](https://github.com/huggingface/safetensors) format:
+//!
+//! ```text
+//! header size  u64, little-endian
+//! header       JSON: {"<name>": {"dtype": "F32", "shape": [2, 3],
+//!                                "data_offsets": [begin, end]}, ...,
+//!                     "__metadata__": {"<key>": "<value>"}}
+//! data         raw little-endian values; offsets are relative to its start
+//! ```
+//!
+//! Only `F32` and `F64` tensors are supported. Module parameters are scalars
+//! with dotted names, so they are grouped into tensors by their trailing
+//! indices: `weights.2.0 .. weights.2.3` of a `Linear` become one tensor
+//! `weights` of shape `[out, in]`. The neurons of an `MLP` layer are gathered
+//! into the layout PyTorch users expect: `layers.0.neurons.2.weights.1`
+//! becomes element `[2, 1]` of `layers.0.weight`, of shape `[out, in]`, and
+//! `layers.0.neurons.2.bias` element `[2]` of `layers.0.bias`, of shape
+//! `[out]`. A name without trailing index, such as the `bias` of a lone
+//! `Neuron`, is a scalar of shape `[]`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::invalid;
+use super::json::{self, Value};
+use crate::nn::state::{IncompatibleKeys, StateDict};
+use crate::nn::Module;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dtype {
+    F32,
+    F64,
+}
+
+impl Dtype {
+    pub fn size(self) -> usize {
+        match self {
+            Dtype::F32 => 4,
+            Dtype::F64 => 8,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Dtype::F32 => "F32",
+            Dtype::F64 => "F64",
+        }
+    }
+}
+
+/// One tensor of a safetensors file. Values are held as `f64`, which
+/// represents both dtypes exactly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TensorData {
+    pub dtype: Dtype,
+    pub shape: Vec<usize>,
+    pub values: Vec<f64>,
+}
+
+/// Largest header accepted, as in the reference implementation.
+const MAX_HEADER: u64 = 100_000_000;
+
+pub fn serialize(
+    tensors: &BTreeMap<String, TensorData>,
+    metadata: &BTreeMap<String, String>,
+) -> Vec<u8> {
+    let mut header = String::from("{");
+    if !metadata.is_empty() {
+        header.push_str("\"__metadata__\":{");
+        for (i, (k, v)) in metadata.iter().enumerate() {
+            if i > 0 {
+                header.push(',');
+            }
+            json::write_str(&mut header, k);
+            header.push(':');
+            json::write_str(&mut header, v);
+        }
+        header.push('}');
+    }
+    let mut data = Vec::new();
+    for (i, (name, t)) in tensors.iter().enumerate() {
+        assert_eq!(
+            t.values.len(),
+            t.shape.iter().product::<usize>(),
+            "tensor {} has {} values for shape {:?}",
+            name,
+            t.values.len(),
+            t.shape
+        );
+        let begin = data.len();
+        for &v in t.values.iter() {
+            match t.dtype {
+                Dtype::F32 => data.extend_from_slice(&(v as f32).to_le_bytes()),
+                Dtype::F64 => data.extend_from_slice(&v.to_le_bytes()),
+            }
+        }
+        if i > 0 || !metadata.is_empty() {
+            header.push(',');
+        }
+        json::write_str(&mut header, name);
+        let shape = t.shape.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        header.push_str(&format!(
+            ":{{\"dtype\":\"{}\",\"shape\":[{}],\"data_offsets\":[{},{}]}}",
+            t.dtype.name(),
+            shape.join(","),
+            begin,
+            data.len()
+        ));
+    }
+    header.push('}');
+    // pad with spaces so the data starts 8-byte aligned
+    while header.len() % 8 != 0 {
+        header.push(' ');
+    }
+    let mut out = Vec::with_capacity(8 + header.len() + data.len());
+    out.extend_from_slice(&(header.len() as u64).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(&data);
+    out
+}
+
+/// Parses and validates a safetensors buffer: the header must be a JSON
+/// object, every tensor must be F32 or F64 with a byte range matching its
+/// shape, and the ranges must tile the data section without gaps or overlap.
+pub fn deserialize(
+    bytes: &[u8],
+) -> io::Result<(BTreeMap<String, TensorData>, BTreeMap<String, String>)> {
+    if bytes.len() < 8 {
+        return invalid("safetensors data is shorter than its 8-byte header size");
+    }
+    let n = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    if n > MAX_HEADER || n > (bytes.len() - 8) as u64 {
+        return invalid(format!(
+            "safetensors header size {} exceeds the {} bytes available",
+            n,
+            bytes.len() - 8
+        ));
+    }
+    let (header, data) = bytes[8..].split_at(n as usize);
+    let header =
+        std::str::from_utf8(header).or_else(|_| invalid("safetensors header is not UTF-8"))?;
+    let header =
+        json::parse(header).or_else(|e| invalid(format!("invalid safetensors header: {}", e)))?;
+    let members = header
+        .as_object()
+        .map_or_else(|| invalid("safetensors header is not a JSON object"), Ok)?;
+
+    let mut metadata = BTreeMap::new();
+    let mut entries = Vec::new();
+    for (name, v) in members.iter() {
+        if name == "__metadata__" {
+            let pairs = v
+                .as_object()
+                .map_or_else(|| invalid("__metadata__ must be an object"), Ok)?;
+            for (k, v) in pairs {
+                let v = v
+                    .as_str()
+                    .map_or_else(|| invalid(format!("metadata {:?} must be a string", k)), Ok)?;
+                metadata.insert(k.clone(), v.to_string());
+            }
+            continue;
+        }
+        entries.push((name.clone(), entry(name, v)?));
+    }
+
+    entries.sort_by_key(|(_, (_, _, begin, _))| *begin);
+    let mut expected = 0;
+    let mut tensors = BTreeMap::new();
+    for (name, (dtype, shape, begin, end)) in entries {
+        let numel = shape
+            .iter()
+            .try_fold(1usize, |acc, &d| acc.checked_mul(d))
+            .and_then(|n| n.checked_mul(dtype.size()));
+        if numel != Some(end - begin) {
+            return invalid(format!(
+                "tensor {:?} of shape {:?} and dtype {} needs {:?} bytes, but its offsets span {}",
+                name,
+                shape,
+                dtype.name(),
+                numel,
+                end - begin
+            ));
+        }
+        if begin != expected {
+            return invalid(format!(
+                "tensor {:?} starts at byte {}, expected {} (gap or overlap in the data)",
+                name, begin, expected
+            ));
+        }
+        if end > data.len() {
+            return invalid(format!(
+                "tensor {:?} ends at byte {}, past the {} bytes of data",
+                name,
+                end,
+                data.len()
+            ));
+        }
+        expected = end;
+        let raw = &data[begin..end];
+        let values = match dtype {
+            Dtype::F32 => raw
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()) as f64)
+                .collect(),
+            Dtype::F64 => raw
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+        };
+        if tensors
+            .insert(
+                name.clone(),
+                TensorData {
+                    dtype,
+                    shape,
+                    values,
+                },
+            )
+            .is_some()
+        {
+            return invalid(format!("tensor {:?} appears twice in the header", name));
+        }
+    }
+    if expected != data.len() {
+        return invalid(format!(
+            "{} bytes of data are not covered by any tensor",
+            data.len() - expected
+        ));
+    }
+    Ok((tensors, metadata))
+}
+
+type Entry = (Dtype, Vec<usize>, usize, usize);
+
+fn entry(name: &str, v: &Value) -> io::Result<Entry> {
+    let dtype = match v.get("dtype").and_then(Value::as_str) {
+        Some("F32") => Dtype::F32,
+        Some("F64") => Dtype::F64,
+        Some(other) => {
+            return invalid(format!(
+                "tensor {:?} has unsupported dtype {} (only F32 and F64)",
+                name, other
+            ))
+        }
+        None => return invalid(format!("tensor {:?} has no dtype", name)),
+    };
+    let numbers = |key: &str| {
+        v.get(key)
+            .and_then(Value::as_array)
+            .and_then(|xs| {
+                xs.iter()
+                    .map(|x| x.as_u64().map(|n| n as usize))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .map_or_else(
+                || {
+                    invalid(format!(
+                        "tensor {:?} has a missing or invalid {:?}",
+                        name, key
+                    ))
+                },
+                Ok,
+            )
+    };
+    let shape = numbers("shape")?;
+    let offsets = numbers("data_offsets")?;
+    match offsets[..] {
+        [begin, end] if begin <= end => Ok((dtype, shape, begin, end)),
+        _ => invalid(format!(
+            "tensor {:?} has invalid data_offsets {:?}",
+            name, offsets
+        )),
+    }
+}
+
+/// Splits `layers.0.weights.2.1` into the tensor name `layers.0.weights` and
+/// the index `[2, 1]`.
+fn split_name(name: &str) -> (String, Vec<usize>) {
+    let mut parts = name.split('.').collect::<Vec<_>>();
+    let mut index = Vec::new();
+    while parts.len() > 1 {
+        match parts.last().unwrap().parse::<usize>() {
+            Ok(i) => {
+                index.push(i);
+                parts.pop();
+            }
+            Err(_) => break,
+        }
+    }
+    index.reverse();
+    (parts.join("."), index)
+}
+
+/// The name of a parameter as an element of a tensor: the neurons of a layer
+/// are rows of one `weight` matrix and one `bias` vector, so
+/// `layers.0.neurons.2.weights.1` is `layers.0.weight.2.1` and
+/// `layers.0.neurons.2.bias` is `layers.0.bias.2`.
+fn element_name(name: &str) -> String {
+    let parts = name.split('.').collect::<Vec<_>>();
+    let is_index = |s: &str| s.parse::<usize>().is_ok();
+    let n = parts.len();
+    let renamed = if n >= 4
+        && parts[n - 4] == "neurons"
+        && is_index(parts[n - 3])
+        && parts[n - 2] == "weights"
+        && is_index(parts[n - 1])
+    {
+        Some((n - 4, vec!["weight", parts[n - 3], parts[n - 1]]))
+    } else if n >= 3
+        && parts[n - 3] == "neurons"
+        && is_index(parts[n - 2])
+        && parts[n - 1] == "bias"
+    {
+        Some((n - 3, vec!["bias", parts[n - 2]]))
+    } else {
+        None
+    };
+    match renamed {
+        Some((prefix, tail)) => {
+            let mut parts = parts[..prefix].to_vec();
+            parts.extend(tail);
+            parts.join(".")
+        }
+        None => name.to_string(),
+    }
+}
+
+/// Tensor name to its shape and row-major values.
+pub type Grouped = BTreeMap<String, (Vec<usize>, Vec<f32>)>;
+
+/// Groups a state dict into tensors, one per name with its trailing indices removed.
+pub fn group(state: &StateDict) -> io::Result<Grouped> {
+    let mut groups: BTreeMap<String, Vec<(Vec<usize>, f32)>> = BTreeMap::new();
+    for (name, &v) in state.iter() {
+        let (key, index) = split_name(&element_name(name));
+        groups.entry(key).or_default().push((index, v));
+    }
+    groups
+        .into_iter()
+        .map(|(key, mut items)| {
+            let rank = items[0].0.len();
+            if items.iter().any(|(i, _)| i.len() != rank) {
+                return invalid(format!(
+                    "parameters under {:?} have different numbers of indices",
+                    key
+                ));
+            }
+            let shape = (0..rank)
+                .map(|d| items.iter().map(|(i, _)| i[d] + 1).max().unwrap())
+                .collect::<Vec<_>>();
+            if items.len() != shape.iter().product::<usize>() {
+                return invalid(format!(
+                    "parameters under {:?} do not fill a tensor of shape {:?}",
+                    key, shape
+                ));
+            }
+            items.sort_by(|a, b| a.0.cmp(&b.0));
+            Ok((key, (shape, items.into_iter().map(|(_, v)| v).collect())))
+        })
+        .collect()
+}
+
+/// Flat names of every element of a tensor called `key` of `shape`, in row-major order.
+fn element_names(key: &str, shape: &[usize]) -> Vec<String> {
+    let mut names = vec![key.to_string()];
+    for &n in shape {
+        names = names
+            .iter()
+            .flat_map(|prefix| (0..n).map(move |i| format!("{}.{}", prefix, i)))
+            .collect();
+    }
+    names
+}
+
+/// The parameters of `module` grouped into tensors of `dtype`.
+pub(super) fn module_tensors<M: Module + ?Sized>(
+    module: &M,
+    dtype: Dtype,
+) -> io::Result<BTreeMap<String, TensorData>> {
+    Ok(group(&module.state_dict())?
+        .into_iter()
+        .map(|(key, (shape, values))| {
+            let values = values.into_iter().map(f64::from).collect();
+            (
+                key,
+                TensorData {
+                    dtype,
+                    shape,
+                    values,
+                },
+            )
+        })
+        .collect())
+}
+
+pub fn module_to_bytes<M: Module + ?Sized>(module: &M, dtype: Dtype) -> io::Result<Vec<u8>> {
+    let tensors = module_tensors(module, dtype)?;
+    let mut metadata = BTreeMap::new();
+    metadata.insert("format".to_string(), "milligrad".to_string());
+    Ok(serialize(&tensors, &metadata))
+}
+
+/// Loads tensors into the parameters of `module`. A tensor whose shape
+/// differs from the model's is always an error; missing or unexpected
+/// tensors are an error only with `strict`, and are reported otherwise.
+pub fn load_module_from_bytes<M: Module + ?Sized>(
+    module: &mut M,
+    bytes: &[u8],
+    strict: bool,
+) -> io::Result<IncompatibleKeys> {
+    load_tensors(module, &deserialize(bytes)?.0, strict)
+}
+
+pub(super) fn load_tensors<M: Module + ?Sized>(
+    module: &mut M,
+    tensors: &BTreeMap<String, TensorData>,
+    strict: bool,
+) -> io::Result<IncompatibleKeys> {
+    let params = module.state_dict();
+    let expected = group(&params)?;
+    // element names back to the parameter names of this module
+    let names = params
+        .keys()
+        .map(|p| (element_name(p), p.clone()))
+        .collect::<HashMap<_, _>>();
+    let mut state = StateDict::new();
+    for (key, t) in tensors.iter() {
+        if let Some((shape, _)) = expected.get(key) {
+            if *shape != t.shape {
+                return invalid(format!(
+                    "shape mismatch for {:?}: file has {:?}, model has {:?}",
+                    key, t.shape, shape
+                ));
+            }
+        }
+        for (name, &v) in element_names(key, &t.shape)
+            .into_iter()
+            .zip(t.values.iter())
+        {
+            let name = names.get(&name).cloned().unwrap_or(name);
+            state.insert(name, v as f32);
+        }
+    }
+    let mut keys = module
+        .load_state_dict(&state, strict)
+        .or_else(|e| invalid(e.to_string()))?;
+    // report whole tensors rather than each of their elements
+    for names in [&mut keys.missing, &mut keys.unexpected] {
+        let mut grouped = names
+            .iter()
+            .map(|n| split_name(&element_name(n)).0)
+            .collect::<Vec<_>>();
+        grouped.sort();
+        grouped.dedup();
+        *names = grouped;
+    }
+    Ok(keys)
+}
+
+pub fn save_module<M: Module + ?Sized, P: AsRef<Path>>(
+    module: &M,
+    path: P,
+    dtype: Dtype,
+) -> io::Result<()> {
+    fs::write(path, module_to_bytes(module, dtype)?)
+}
+
+pub fn load_module<M: Module + ?Sized, P: AsRef<Path>>(
+    module: &mut M,
+    path: P,
+    strict: bool,
+) -> io::Result<IncompatibleKeys> {
+    load_module_from_bytes(module, &fs::read(path)?, strict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::linear::Linear;
+    use crate::nn::mlp::MLP;
+    use crate::nn::sequential::Sequential;
+
+    fn bits(m: &dyn Module) -> Vec<u32> {
+        m.parameters().iter().map(|p| p.data().to_bits()).collect()
+    }
+
+    #[test]
+    fn test_module_round_trip_f32_and_f64() {
+        let a = MLP::new(3, vec![4], 2);
+        for dtype in [Dtype::F32, Dtype::F64] {
+            let mut b = MLP::new(3, vec![4], 2);
+            let bytes = module_to_bytes(&a, dtype).unwrap();
+            let keys = load_module_from_bytes(&mut b, &bytes, true).unwrap();
+            assert!(keys.is_empty());
+            assert_eq!(bits(&a), bits(&b));
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("milligrad-{}.safetensors", rand::random::<u64>()));
+        let seq = Sequential::new().linear(3, 2).relu().linear(2, 1);
+        save_module(&seq, &path, Dtype::F32).unwrap();
+        let (tensors, metadata) = deserialize(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(tensors["0.weights"].shape, vec![2, 3]);
+        assert_eq!(tensors["2.bias"].shape, vec![1]);
+        assert_eq!(metadata["format"], "milligrad");
+
+        let mut other = Sequential::new().linear(3, 2).relu().linear(2, 1);
+        load_module(&mut other, &path, true).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bits(&seq), bits(&other));
+    }
+
+    #[test]
+    fn test_mlp_layers_are_matrices() {
+        let mlp = MLP::new(3, vec![4], 2);
+        let (tensors, _) = deserialize(&module_to_bytes(&mlp, Dtype::F32).unwrap()).unwrap();
+        let names = tensors.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "layers.0.bias",
+                "layers.0.weight",
+                "layers.1.bias",
+                "layers.1.weight"
+            ]
+        );
+        assert_eq!(tensors["layers.0.weight"].shape, vec![4, 3]);
+        assert_eq!(tensors["layers.0.bias"].shape, vec![4]);
+        assert_eq!(tensors["layers.1.weight"].shape, vec![2, 4]);
+        let w = mlp.layers[0].neurons[2].weights[1].data();
+        assert_eq!(tensors["layers.0.weight"].values[2 * 3 + 1], w as f64);
+
+        // a model with an extra layer reports whole tensors as missing
+        let shallow = MLP::new(3, vec![4], 5);
+        let mut deeper = MLP::new(3, vec![4, 5], 2);
+        let bytes = module_to_bytes(&shallow, Dtype::F32).unwrap();
+        let keys = load_module_from_bytes(&mut deeper, &bytes, false).unwrap();
+        assert_eq!(keys.missing, vec!["layers.2.bias", "layers.2.weight"]);
+        assert!(keys.unexpected.is_empty());
+        assert_eq!(bits(&deeper.layers[1]), bits(&shallow.layers[1]));
+    }
+
+    #[test]
+    fn test_layout_matches_reference() {
+        let mut tensors = BTreeMap::new();
+        tensors.insert(
+            "w".to_string(),
+            TensorData {
+                dtype: Dtype::F64,
+                shape: vec![2],
+                values: vec![1.0, -2.0],
+            },
+        );
+        let bytes = serialize(&tensors, &BTreeMap::new());
+        let header = r#"{"w":{"dtype":"F64","shape":[2],"data_offsets":[0,16]}}"#;
+        let n = u64::from_le_bytes(bytes[..8].try_into().unwrap()) as usize;
+        assert_eq!(n % 8, 0);
+        assert_eq!(
+            std::str::from_utf8(&bytes[8..8 + n]).unwrap().trim_end(),
+            header
+        );
+        assert_eq!(&bytes[8 + n..8 + n + 8], &1.0f64.to_le_bytes());
+        assert_eq!(deserialize(&bytes).unwrap().0, tensors);
+    }
+
+    fn file(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut out = (header.len() as u64).to_le_bytes().to_vec();
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    #[test]
+    fn test_rejects_bad_offsets_and_shapes() {
+        let err = |b: &[u8]| deserialize(b).unwrap_err().to_string();
+        let t = |offsets: &str, shape: &str| {
+            format!(
+                r#"{{"a":{{"dtype":"F32","shape":{},"data_offsets":{}}}}}"#,
+                shape, offsets
+            )
+        };
+        assert!(err(&file(&t("[0,8]", "[3]"), &[0; 8]))
+            .contains("needs Some(12) bytes, but its offsets span 8"));
+        assert!(err(&file(&t("[4,12]", "[2]"), &[0; 12])).contains("starts at byte 4, expected 0"));
+        assert!(err(&file(&t("[0,8]", "[2]"), &[0; 4])).contains("past the 4 bytes of data"));
+        assert!(
+            err(&file(&t("[0,8]", "[2]"), &[0; 12])).contains("4 bytes of data are not covered")
+        );
+        assert!(err(&file(&t("[8,0]", "[2]"), &[0; 8])).contains("invalid data_offsets"));
+        let bf16 = r#"{"a":{"dtype":"BF16","shape":[1],"data_offsets":[0,2]}}"#;
+        assert!(err(&file(bf16, &[0; 2])).contains("unsupported dtype BF16"));
+        assert!(err(&[200, 0, 0, 0, 0, 0, 0, 0, b'{']).contains("exceeds the 1 bytes available"));
+        assert!(err(&file(&"[".repeat(200_000), &[])).contains("nesting too deep"));
+
+        let mut small = Linear::new(2, 2, true);
+        let big = Linear::new(3, 2, true);
+        let bytes = module_to_bytes(&big, Dtype::F32).unwrap();
+        let msg = load_module_from_bytes(&mut small, &bytes, false)
+            .unwrap_err()
+            .to_string();
+        assert!(msg.contains("shape mismatch for \"weights\": file has [2, 3], model has [2, 2]"));
+    }
+
+    #[test]
+    fn test_strict_and_relaxed_keys() {
+        let mut with_bias = Linear::new(2, 2, true);
+        let without = Linear::new(2, 2, false);
+        let bytes = module_to_bytes(&without, Dtype::F32).unwrap();
+        let msg = load_module_from_bytes(&mut with_bias, &bytes, true)
+            .unwrap_err()
+            .to_string();
+        assert!(msg.contains("missing keys [\"bias.0\", \"bias.1\"]"));
+
+        let keys = load_module_from_bytes(&mut with_bias, &bytes, false).unwrap();
+        assert_eq!(keys.missing, vec!["bias".to_string()]);
+        assert_eq!(bits(&with_bias)[..4], bits(&without)[..]);
+    }
+}
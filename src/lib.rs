@@ -1,3 +1,4 @@
 pub mod fundamental;
+pub mod io;
 pub mod nn;
-mod optim;
+pub mod optim;
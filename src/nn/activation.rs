@@ -0,0 +1,89 @@
+use crate::fundamental::op::*;
+use crate::fundamental::Unit;
+use crate::nn::Module;
+
+/// Non-linearity applied to the output of a `Neuron`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    Identity,
+    ReLU,
+    LeakyReLU(f32),
+    Tanh,
+    Sigmoid,
+    GELU,
+    SiLU,
+    ELU(f32),
+}
+
+impl Activation {
+    pub fn apply(&self, x: &Unit) -> Unit {
+        match *self {
+            Activation::Identity => x.clone(),
+            Activation::ReLU => relu(x),
+            Activation::LeakyReLU(alpha) => leaky_relu(x, alpha),
+            Activation::Tanh => tanh(x),
+            Activation::Sigmoid => sigmoid(x),
+            Activation::GELU => gelu(x),
+            Activation::SiLU => silu(x),
+            Activation::ELU(alpha) => elu(x, alpha),
+        }
+    }
+}
+
+/// Applied element-wise, so an activation can sit between layers of a `Sequential`.
+impl Module for Activation {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        input.iter().map(|x| self.apply(x)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::unit::new_unit;
+
+    const ALL: [Activation; 8] = [
+        Activation::Identity,
+        Activation::ReLU,
+        Activation::LeakyReLU(0.1),
+        Activation::Tanh,
+        Activation::Sigmoid,
+        Activation::GELU,
+        Activation::SiLU,
+        Activation::ELU(1.0),
+    ];
+
+    #[test]
+    fn test_activation_gradients_match_finite_differences() {
+        let eps = 1e-3;
+        for act in ALL.iter() {
+            for &v in [-1.5f32, -0.3, 0.4, 2.0].iter() {
+                let x = new_unit(v);
+                let y = act.apply(&x);
+                backward(&y);
+                let numeric = (act.apply(&new_unit(v + eps)).data()
+                    - act.apply(&new_unit(v - eps)).data())
+                    / (2.0 * eps);
+                assert!(
+                    (x.grad() - numeric).abs() < 1e-2,
+                    "{:?} at {}: {} vs {}",
+                    act,
+                    v,
+                    x.grad(),
+                    numeric
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_output_ranges() {
+        assert_eq!(Activation::Sigmoid.apply(&new_unit(0.0)).data(), 0.5);
+        assert_eq!(Activation::Sigmoid.apply(&new_unit(-200.0)).data(), 0.0);
+        assert_eq!(Activation::Tanh.apply(&new_unit(30.0)).data(), 1.0);
+        assert_eq!(
+            Activation::LeakyReLU(0.1).apply(&new_unit(-2.0)).data(),
+            -0.2
+        );
+    }
+}
@@ -0,0 +1,355 @@
+use super::*;
+use crate::fundamental::op::{add, dot, mul, softmax};
+use crate::nn::activation::Activation;
+use crate::nn::init::Init;
+use crate::nn::linear::Linear;
+use crate::nn::norm::LayerNorm;
+use crate::nn::sequential::Sequential;
+
+/// Splits a flat, token-major input into tokens of `dim` values each.
+fn tokens(name: &str, input: &[Unit], dim: usize) -> Vec<Vec<Unit>> {
+    assert!(
+        !input.is_empty() && input.len().is_multiple_of(dim),
+        "{} expects a sequence of tokens with {} values each, got {} values",
+        name,
+        dim,
+        input.len()
+    );
+    input.chunks(dim).map(|t| t.to_vec()).collect()
+}
+
+/// `softmax(q k^T / sqrt(d)) v` for one sequence, one vector per token. With
+/// `causal` each query only sees keys at its own position and before, as if
+/// the later scores were masked to minus infinity.
+pub fn scaled_dot_product_attention(
+    q: &[Vec<Unit>],
+    k: &[Vec<Unit>],
+    v: &[Vec<Unit>],
+    causal: bool,
+) -> Vec<Vec<Unit>> {
+    assert_eq!(
+        k.len(),
+        v.len(),
+        "attention needs one value per key, got {} keys and {} values",
+        k.len(),
+        v.len()
+    );
+    if causal {
+        assert_eq!(
+            q.len(),
+            k.len(),
+            "causal attention needs as many queries as keys, got {} and {}",
+            q.len(),
+            k.len()
+        );
+    }
+    let dim = q.first().map_or(1, |t| t.len());
+    let scale = Unit::from(1.0 / (dim as f32).sqrt());
+    let value_dim = v.first().map_or(0, |t| t.len());
+    q.iter()
+        .enumerate()
+        .map(|(i, qi)| {
+            let seen = if causal { i + 1 } else { k.len() };
+            let scores = k[..seen]
+                .iter()
+                .map(|kj| mul(&dot(qi, kj), &scale))
+                .collect::<Vec<_>>();
+            let weights = softmax(&scores);
+            (0..value_dim)
+                .map(|c| {
+                    let column = v[..seen].iter().map(|vj| vj[c].clone()).collect::<Vec<_>>();
+                    dot(&weights, &column)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Self-attention with `num_heads` heads of `embed_dim / num_heads` values,
+/// each attending over its slice of the query, key and value projections.
+/// As a `Module` the input is a flat sequence of `embed_dim`-sized tokens.
+#[derive(Debug)]
+pub struct MultiHeadAttention {
+    pub embed_dim: usize,
+    pub num_heads: usize,
+    pub causal: bool,
+    pub query: Linear,
+    pub key: Linear,
+    pub value: Linear,
+    pub out: Linear,
+}
+
+impl MultiHeadAttention {
+    pub fn new(embed_dim: usize, num_heads: usize, causal: bool) -> Self {
+        assert!(
+            num_heads > 0 && embed_dim.is_multiple_of(num_heads),
+            "embed_dim {} must be divisible by num_heads {}",
+            embed_dim,
+            num_heads
+        );
+        let projection = || Linear::with_init(embed_dim, embed_dim, true, &Init::XavierUniform);
+        MultiHeadAttention {
+            embed_dim,
+            num_heads,
+            causal,
+            query: projection(),
+            key: projection(),
+            value: projection(),
+            out: projection(),
+        }
+    }
+
+    pub fn attend(&self, xs: &[Vec<Unit>]) -> Vec<Vec<Unit>> {
+        let q = xs.iter().map(|x| self.query.eval(x)).collect::<Vec<_>>();
+        let k = xs.iter().map(|x| self.key.eval(x)).collect::<Vec<_>>();
+        let v = xs.iter().map(|x| self.value.eval(x)).collect::<Vec<_>>();
+        let head_dim = self.embed_dim / self.num_heads;
+        let head = |ts: &[Vec<Unit>], h: usize| {
+            ts.iter()
+                .map(|t| t[h * head_dim..(h + 1) * head_dim].to_vec())
+                .collect::<Vec<_>>()
+        };
+        let mut merged = vec![Vec::with_capacity(self.embed_dim); xs.len()];
+        for h in 0..self.num_heads {
+            let heads =
+                scaled_dot_product_attention(&head(&q, h), &head(&k, h), &head(&v, h), self.causal);
+            for (m, o) in merged.iter_mut().zip(heads) {
+                m.extend(o);
+            }
+        }
+        merged.iter().map(|m| self.out.eval(m)).collect()
+    }
+}
+
+impl Module for MultiHeadAttention {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        let xs = tokens("MultiHeadAttention", input, self.embed_dim);
+        self.attend(&xs).concat()
+    }
+
+    fn children(&self) -> Vec<(String, &dyn Module)> {
+        vec![
+            ("query".to_string(), &self.query),
+            ("key".to_string(), &self.key),
+            ("value".to_string(), &self.value),
+            ("out".to_string(), &self.out),
+        ]
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+        vec![
+            &mut self.query,
+            &mut self.key,
+            &mut self.value,
+            &mut self.out,
+        ]
+    }
+}
+
+/// Pre-norm transformer block:
+///
+/// ```text
+/// x = x + attention(layer_norm(x))
+/// x = x + mlp(layer_norm(x))
+/// ```
+///
+/// where the MLP widens each token to `4 * embed_dim` with a GELU in between.
+pub struct TransformerBlock {
+    pub ln1: LayerNorm,
+    pub attention: MultiHeadAttention,
+    pub ln2: LayerNorm,
+    pub mlp: Sequential,
+}
+
+impl TransformerBlock {
+    pub fn new(embed_dim: usize, num_heads: usize, causal: bool) -> Self {
+        let hidden = 4 * embed_dim;
+        let mlp = Sequential::new()
+            .then(Linear::with_init(
+                embed_dim,
+                hidden,
+                true,
+                &Init::XavierUniform,
+            ))
+            .activation(Activation::GELU)
+            .then(Linear::with_init(
+                hidden,
+                embed_dim,
+                true,
+                &Init::XavierUniform,
+            ));
+        TransformerBlock {
+            ln1: LayerNorm::new(embed_dim),
+            attention: MultiHeadAttention::new(embed_dim, num_heads, causal),
+            ln2: LayerNorm::new(embed_dim),
+            mlp,
+        }
+    }
+
+    pub fn embed_dim(&self) -> usize {
+        self.attention.embed_dim
+    }
+
+    pub fn attend(&self, xs: &[Vec<Unit>]) -> Vec<Vec<Unit>> {
+        let normed = xs.iter().map(|x| self.ln1.forward(x)).collect::<Vec<_>>();
+        let xs = residual(xs, &self.attention.attend(&normed));
+        let ys = xs
+            .iter()
+            .map(|x| self.mlp.forward(&self.ln2.forward(x)))
+            .collect::<Vec<_>>();
+        residual(&xs, &ys)
+    }
+}
+
+fn residual(xs: &[Vec<Unit>], fx: &[Vec<Unit>]) -> Vec<Vec<Unit>> {
+    xs.iter()
+        .zip(fx.iter())
+        .map(|(x, f)| x.iter().zip(f.iter()).map(|(a, b)| add(a, b)).collect())
+        .collect()
+}
+
+impl Module for TransformerBlock {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        let xs = tokens("TransformerBlock", input, self.embed_dim());
+        self.attend(&xs).concat()
+    }
+
+    fn children(&self) -> Vec<(String, &dyn Module)> {
+        vec![
+            ("ln1".to_string(), &self.ln1),
+            ("attention".to_string(), &self.attention),
+            ("ln2".to_string(), &self.ln2),
+            ("mlp".to_string(), &self.mlp),
+        ]
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+        vec![
+            &mut self.ln1,
+            &mut self.attention,
+            &mut self.ln2,
+            &mut self.mlp,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::backward;
+    use crate::fundamental::unit::new_unit;
+    use crate::nn::embedding::Embedding;
+    use crate::nn::loss::{cross_entropy, Reduction};
+
+    fn sequence(tokens: &[&[f32]]) -> Vec<Vec<Unit>> {
+        tokens
+            .iter()
+            .map(|t| t.iter().map(|&v| new_unit(v)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_attention_weights_and_causal_mask() {
+        let q = sequence(&[&[0.0, 0.0], &[0.0, 0.0]]);
+        let k = sequence(&[&[1.0, 0.0], &[0.0, 1.0]]);
+        let v = sequence(&[&[2.0, 0.0], &[0.0, 4.0]]);
+        // equal scores: the plain average of the values
+        let out = scaled_dot_product_attention(&q, &k, &v, false);
+        assert_eq!(out[1][0].data(), 1.0);
+        assert_eq!(out[1][1].data(), 2.0);
+
+        let out = scaled_dot_product_attention(&q, &k, &v, true);
+        assert_eq!(out[0][0].data(), 2.0);
+        assert_eq!(out[0][1].data(), 0.0);
+        backward(&out[0][0]);
+        assert!(v[1].iter().chain(k[1].iter()).all(|u| u.grad() == 0.0));
+    }
+
+    #[test]
+    fn test_causal_block_ignores_future_tokens() {
+        let block = TransformerBlock::new(4, 2, true);
+        assert_eq!(
+            block.num_parameters(),
+            2 * 8 + 4 * (16 + 4) + (4 * 16 + 16) + (16 * 4 + 4)
+        );
+        assert!(block
+            .named_parameters()
+            .iter()
+            .any(|(name, _)| name == "attention.query.weights.0.0"));
+
+        let x = sequence(&[
+            &[0.1, 0.2, 0.3, 0.4],
+            &[-0.5, 0.1, 0.0, 0.2],
+            &[0.3, -0.3, 0.8, 0.1],
+        ]);
+        let y = block.attend(&x);
+        assert_eq!(y.len(), 3);
+        backward(&y[1][2]);
+        assert!(x[0].iter().chain(x[1].iter()).any(|u| u.grad() != 0.0));
+        assert!(x[2].iter().all(|u| u.grad() == 0.0));
+        assert_eq!(block.forward(&x.concat()).len(), 12);
+    }
+
+    #[test]
+    #[should_panic(expected = "embed_dim 6 must be divisible by num_heads 4")]
+    fn test_heads_must_divide_embed_dim() {
+        MultiHeadAttention::new(6, 4, false);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "TransformerBlock expects a sequence of tokens with 4 values each, got 6 values"
+    )]
+    fn test_partial_token() {
+        TransformerBlock::new(4, 1, true).forward(&sequence(&[&[0.0; 6]]).concat());
+    }
+
+    /// A tiny GPT: token and position embeddings, one causal block and a
+    /// linear head predicting the next character at every position.
+    #[test]
+    fn test_char_level_next_token_loss_goes_down() {
+        let text = "abcabdabcabd";
+        let vocab = ['a', 'b', 'c', 'd'];
+        let ids = text
+            .chars()
+            .map(|c| vocab.iter().position(|&v| v == c).unwrap())
+            .collect::<Vec<_>>();
+        let (dim, context) = (8, 4);
+        let tokens = Embedding::with_init(vocab.len(), dim, &Init::XavierUniform);
+        let positions = Embedding::with_init(context, dim, &Init::XavierUniform);
+        let block = TransformerBlock::new(dim, 2, true);
+        let head = Linear::with_init(dim, vocab.len(), true, &Init::XavierUniform);
+
+        let windows = ids.windows(context + 1).step_by(2).collect::<Vec<_>>();
+        let loss = || {
+            let mut logits = Vec::new();
+            let mut targets = Vec::new();
+            for w in windows.iter() {
+                let xs = (0..context)
+                    .map(|t| {
+                        let (tok, pos) = (tokens.lookup(w[t]), positions.lookup(t));
+                        tok.iter().zip(pos.iter()).map(|(a, b)| add(a, b)).collect()
+                    })
+                    .collect::<Vec<Vec<Unit>>>();
+                for (t, h) in block.attend(&xs).iter().enumerate() {
+                    logits.push(head.eval(h));
+                    targets.push(w[t + 1]);
+                }
+            }
+            cross_entropy(&logits, &targets, Reduction::Mean).remove(0)
+        };
+
+        let mut params = tokens.parameters();
+        params.extend(positions.parameters());
+        params.extend(block.parameters());
+        params.extend(head.parameters());
+        let first = loss().data();
+        for _ in 0..30 {
+            params.iter().for_each(|p| p.zero_grad());
+            backward(&loss());
+            params.iter().for_each(|p| p.adjust(-0.1));
+        }
+        let last = loss().data();
+        assert!(last < first * 0.7, "loss went from {} to {}", first, last);
+    }
+}
@@ -0,0 +1,255 @@
+use super::*;
+use crate::fundamental::op::add;
+
+/// Skip connection around a module: `x + f(x)`. The wrapped module must
+/// produce as many outputs as it takes inputs.
+pub struct Residual {
+    pub inner: Box<dyn Module>,
+}
+
+impl Residual {
+    pub fn new<M: Module + 'static>(module: M) -> Self {
+        Residual::from_boxed(Box::new(module))
+    }
+
+    pub fn from_boxed(inner: Box<dyn Module>) -> Self {
+        if let Some(n) = inner.in_features() {
+            check_residual(n, inner.out_features(n));
+        }
+        Residual { inner }
+    }
+}
+
+fn check_residual(inputs: usize, outputs: usize) {
+    assert_eq!(
+        inputs, outputs,
+        "Residual needs a module that keeps the size, but it maps {} inputs to {} outputs",
+        inputs, outputs
+    );
+}
+
+fn skip(x: &[Unit], fx: &[Unit]) -> Vec<Unit> {
+    check_residual(x.len(), fx.len());
+    x.iter().zip(fx.iter()).map(|(a, b)| add(a, b)).collect()
+}
+
+impl Module for Residual {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        skip(input, &self.inner.forward(input))
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Unit>]) -> Vec<Vec<Unit>> {
+        let fx = self.inner.forward_batch(batch);
+        batch
+            .iter()
+            .zip(fx.iter())
+            .map(|(x, f)| skip(x, f))
+            .collect()
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        self.inner.in_features()
+    }
+
+    fn children(&self) -> Vec<(String, &dyn Module)> {
+        vec![("inner".to_string(), self.inner.as_ref())]
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+        vec![self.inner.as_mut()]
+    }
+}
+
+/// Feeds the same input to every branch and concatenates their outputs in
+/// order, e.g. to combine paths of different depth. Branches are named
+/// `0`, `1`, ... like the modules of a `Sequential`.
+#[derive(Default)]
+pub struct Parallel {
+    pub branches: Vec<Box<dyn Module>>,
+}
+
+impl Parallel {
+    pub fn new() -> Self {
+        Parallel::default()
+    }
+
+    /// Adds a branch, checking that it takes the same number of inputs as the others.
+    pub fn branch<M: Module + 'static>(self, module: M) -> Self {
+        self.branch_boxed(Box::new(module))
+    }
+
+    pub fn branch_boxed(mut self, module: Box<dyn Module>) -> Self {
+        if let (Some(required), Some(expected)) = (module.in_features(), self.in_features()) {
+            assert_eq!(
+                required,
+                expected,
+                "branch {} of Parallel takes {} inputs, but the other branches take {}",
+                self.branches.len(),
+                required,
+                expected
+            );
+        }
+        self.branches.push(module);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.branches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty()
+    }
+}
+
+impl Module for Parallel {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        assert!(!self.branches.is_empty(), "Parallel has no branches");
+        self.branches
+            .iter()
+            .flat_map(|b| b.forward(input))
+            .collect()
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Unit>]) -> Vec<Vec<Unit>> {
+        assert!(!self.branches.is_empty(), "Parallel has no branches");
+        let mut output = vec![Vec::new(); batch.len()];
+        for b in self.branches.iter() {
+            for (out, y) in output.iter_mut().zip(b.forward_batch(batch)) {
+                out.extend(y);
+            }
+        }
+        output
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        self.branches.iter().find_map(|b| b.in_features())
+    }
+
+    fn out_features(&self, input: usize) -> usize {
+        self.branches.iter().map(|b| b.out_features(input)).sum()
+    }
+
+    fn children(&self) -> Vec<(String, &dyn Module)> {
+        self.branches
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (i.to_string(), b.as_ref()))
+            .collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+        self.branches
+            .iter_mut()
+            .map(|b| b.as_mut() as &mut dyn Module)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::backward;
+    use crate::fundamental::unit::new_unit;
+    use crate::nn::activation::Activation;
+    use crate::nn::init::Init;
+    use crate::nn::linear::Linear;
+    use crate::nn::mlp::Layer;
+    use crate::nn::sequential::Sequential;
+    use std::collections::HashSet;
+
+    fn units(values: &[f32]) -> Vec<Unit> {
+        values.iter().map(|&v| new_unit(v)).collect()
+    }
+
+    #[test]
+    fn test_residual_adds_input() {
+        let block = Residual::new(Linear::with_init(2, 2, false, &Init::Constant(1.0)));
+        let x = units(&[1.0, 2.0]);
+        let y = block.forward(&x);
+        assert_eq!(y[0].data(), 4.0);
+        assert_eq!(y[1].data(), 5.0);
+
+        backward(&y[0]);
+        // one path through the skip, one through the linear layer
+        assert_eq!(x[0].grad(), 2.0);
+        assert_eq!(x[1].grad(), 1.0);
+        assert_eq!(block.named_parameters()[0].0, "inner.weights.0.0");
+    }
+
+    #[test]
+    fn test_parallel_concatenates_branches() {
+        let par = Parallel::new()
+            .branch(Linear::with_init(3, 2, false, &Init::Constant(1.0)))
+            .branch(Activation::ReLU)
+            .branch(Layer::new(3, 1, Activation::Tanh));
+        assert_eq!(par.in_features(), Some(3));
+        assert_eq!(par.out_features(3), 2 + 3 + 1);
+
+        let y = par.forward(&units(&[1.0, -2.0, 3.0]));
+        let values = y.iter().map(|u| u.data()).collect::<Vec<_>>();
+        assert_eq!(&values[..5], &[2.0, 2.0, 1.0, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn test_deep_network_lists_each_parameter_once() {
+        let model = Sequential::new()
+            .linear(4, 8)
+            .then(Residual::new(
+                Sequential::new().linear(8, 8).relu().linear(8, 8),
+            ))
+            .then(
+                Parallel::new()
+                    .branch(Linear::new(8, 2, true))
+                    .branch(Residual::new(Layer::new(8, 8, Activation::Tanh))),
+            )
+            .linear(10, 1);
+        let expected = (4 * 8 + 8) + 2 * (8 * 8 + 8) + (8 * 2 + 2) + (8 * 8 + 8) + (10 + 1);
+        let params = model.named_parameters();
+        assert_eq!(params.len(), expected);
+        let names = params
+            .iter()
+            .map(|(n, _)| n.clone())
+            .collect::<HashSet<_>>();
+        let units = params
+            .iter()
+            .map(|(_, p)| p.as_ptr())
+            .collect::<HashSet<_>>();
+        assert_eq!(names.len(), expected);
+        assert_eq!(units.len(), expected);
+        assert!(names.contains("1.inner.2.bias.7"));
+        assert!(names.contains("2.1.inner.neurons.0.weights.3"));
+
+        let y = model.forward(&units_of(4));
+        assert_eq!(y.len(), 1);
+    }
+
+    fn units_of(n: usize) -> Vec<Unit> {
+        (0..n).map(|i| new_unit(i as f32 * 0.1)).collect()
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Residual needs a module that keeps the size, but it maps 3 inputs to 2 outputs"
+    )]
+    fn test_residual_size_change() {
+        Residual::new(Linear::new(3, 2, true));
+    }
+
+    #[test]
+    #[should_panic(expected = "maps 2 inputs to 4 outputs")]
+    fn test_residual_size_change_at_forward() {
+        let doubled = Parallel::new()
+            .branch(Activation::ReLU)
+            .branch(Activation::Tanh);
+        Residual::new(doubled).forward(&units(&[1.0, 2.0]));
+    }
+
+    #[test]
+    #[should_panic(expected = "branch 1 of Parallel takes 2 inputs, but the other branches take 3")]
+    fn test_parallel_input_mismatch() {
+        Parallel::new()
+            .branch(Linear::new(3, 1, true))
+            .branch(Linear::new(2, 1, true));
+    }
+}
@@ -0,0 +1,490 @@
+use super::*;
+use crate::fundamental::op::{add, dot};
+use crate::fundamental::unit::new_unit;
+use crate::nn::init::{Fan, Init};
+
+/// How a kernel slides over a 2-D plane. 1-D layers use a height of 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Window {
+    pub kernel: (usize, usize),
+    pub stride: (usize, usize),
+    pub padding: (usize, usize),
+    pub dilation: (usize, usize),
+}
+
+impl Window {
+    pub fn new(kernel: (usize, usize)) -> Self {
+        Window {
+            kernel,
+            stride: (1, 1),
+            padding: (0, 0),
+            dilation: (1, 1),
+        }
+    }
+
+    pub fn check(&self, name: &str) {
+        let all = [self.kernel, self.stride, self.dilation];
+        assert!(
+            all.iter().all(|&(a, b)| a > 0 && b > 0),
+            "{}: kernel size, stride and dilation must be positive, got {:?}",
+            name,
+            self
+        );
+    }
+
+    /// Output height and width for an input plane of `input`.
+    pub fn output_size(&self, name: &str, input: (usize, usize)) -> (usize, usize) {
+        let dim = |n: usize, k: usize, s: usize, p: usize, d: usize| {
+            let span = d * (k - 1) + 1;
+            assert!(
+                n + 2 * p >= span,
+                "{}: input of size {} (padded to {}) is smaller than the kernel span {}",
+                name,
+                n,
+                n + 2 * p,
+                span
+            );
+            (n + 2 * p - span) / s + 1
+        };
+        (
+            dim(
+                input.0,
+                self.kernel.0,
+                self.stride.0,
+                self.padding.0,
+                self.dilation.0,
+            ),
+            dim(
+                input.1,
+                self.kernel.1,
+                self.stride.1,
+                self.padding.1,
+                self.dilation.1,
+            ),
+        )
+    }
+
+    /// Positions within one plane under the kernel at output `(oy, ox)`, in
+    /// row-major kernel order; `None` where the kernel covers padding.
+    pub fn taps(&self, input: (usize, usize), oy: usize, ox: usize) -> Vec<Option<usize>> {
+        let mut taps = Vec::with_capacity(self.kernel.0 * self.kernel.1);
+        for ky in 0..self.kernel.0 {
+            let y = (oy * self.stride.0 + ky * self.dilation.0).checked_sub(self.padding.0);
+            for kx in 0..self.kernel.1 {
+                let x = (ox * self.stride.1 + kx * self.dilation.1).checked_sub(self.padding.1);
+                taps.push(match (y, x) {
+                    (Some(y), Some(x)) if y < input.0 && x < input.1 => Some(y * input.1 + x),
+                    _ => None,
+                });
+            }
+        }
+        taps
+    }
+}
+
+/// Shared by `Conv1d` and `Conv2d`: the input is channel-major, `channels`
+/// planes of `input.0 * input.1` values, and so is the output.
+#[derive(Debug)]
+struct Conv {
+    in_channels: usize,
+    out_channels: usize,
+    window: Window,
+    /// one row of `in_channels * kernel` weights per output channel
+    weights: Vec<Vec<Unit>>,
+    bias: Option<Vec<Unit>>,
+}
+
+impl Conv {
+    fn new(
+        in_channels: usize,
+        out_channels: usize,
+        kernel: (usize, usize),
+        bias: bool,
+        init: &Init,
+    ) -> Self {
+        let taps = kernel.0 * kernel.1;
+        let fan = Fan {
+            fan_in: in_channels * taps,
+            fan_out: out_channels * taps,
+        };
+        let weights = init
+            .sample_rows(out_channels, fan)
+            .into_iter()
+            .map(|row| row.into_iter().map(new_unit).collect())
+            .collect();
+        let bias = if bias {
+            Some((0..out_channels).map(|_| new_unit(0.0)).collect())
+        } else {
+            None
+        };
+        Conv {
+            in_channels,
+            out_channels,
+            window: Window::new(kernel),
+            weights,
+            bias,
+        }
+    }
+
+    fn eval(&self, name: &str, input: &[Unit], size: (usize, usize)) -> Vec<Unit> {
+        self.window.check(name);
+        let plane = size.0 * size.1;
+        assert_eq!(
+            input.len(),
+            self.in_channels * plane,
+            "{} expects {} channels of {}x{} = {} inputs, got {}",
+            name,
+            self.in_channels,
+            size.0,
+            size.1,
+            self.in_channels * plane,
+            input.len()
+        );
+        let (oh, ow) = self.window.output_size(name, size);
+        let zero = &Unit::from(0.0);
+        let mut patches = Vec::with_capacity(oh * ow);
+        for oy in 0..oh {
+            for ox in 0..ow {
+                let taps = self.window.taps(size, oy, ox);
+                let patch = (0..self.in_channels)
+                    .flat_map(|c| {
+                        taps.iter().map(move |t| match t {
+                            Some(i) => input[c * plane + i].clone(),
+                            None => zero.clone(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                patches.push(patch);
+            }
+        }
+        let mut output = Vec::with_capacity(self.out_channels * patches.len());
+        for (o, row) in self.weights.iter().enumerate() {
+            for patch in patches.iter() {
+                let y = dot(row, patch);
+                output.push(match self.bias {
+                    Some(ref b) => add(&y, &b[o]),
+                    None => y,
+                });
+            }
+        }
+        output
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
+        let mut params = Vec::new();
+        for (o, row) in self.weights.iter().enumerate() {
+            for (i, w) in row.iter().enumerate() {
+                params.push((format!("weights.{}.{}", o, i), w.clone()));
+            }
+        }
+        if let Some(ref b) = self.bias {
+            for (o, u) in b.iter().enumerate() {
+                params.push((format!("bias.{}", o), u.clone()));
+            }
+        }
+        params
+    }
+}
+
+/// 1-D convolution over `in_channels` signals stored one after another. The
+/// signal length follows from the input size. Weights are named
+/// `weights.<out>.<in * kernel + k>`.
+#[derive(Debug)]
+pub struct Conv1d {
+    conv: Conv,
+}
+
+impl Conv1d {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: usize) -> Self {
+        Conv1d::with_init(
+            in_channels,
+            out_channels,
+            kernel_size,
+            true,
+            &Init::default(),
+        )
+    }
+
+    pub fn with_init(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        bias: bool,
+        init: &Init,
+    ) -> Self {
+        let conv = Conv::new(in_channels, out_channels, (1, kernel_size), bias, init);
+        Conv1d { conv }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.conv.window.stride.1 = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.conv.window.padding.1 = padding;
+        self
+    }
+
+    pub fn dilation(mut self, dilation: usize) -> Self {
+        self.conv.window.dilation.1 = dilation;
+        self
+    }
+
+    pub fn weights(&self) -> &[Vec<Unit>] {
+        &self.conv.weights
+    }
+
+    pub fn bias(&self) -> Option<&[Unit]> {
+        self.conv.bias.as_deref()
+    }
+
+    /// Output length for signals of `length`.
+    pub fn output_len(&self, length: usize) -> usize {
+        self.conv.window.output_size("Conv1d", (1, length)).1
+    }
+
+    fn length(&self, input: usize) -> usize {
+        let c = self.conv.in_channels;
+        assert!(
+            input.is_multiple_of(c),
+            "Conv1d expects {} channels of equal length, got {} inputs",
+            c,
+            input
+        );
+        input / c
+    }
+}
+
+impl Module for Conv1d {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        let length = self.length(input.len());
+        self.conv.eval("Conv1d", input, (1, length))
+    }
+
+    fn out_features(&self, input: usize) -> usize {
+        self.conv.out_channels * self.output_len(self.length(input))
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
+        self.conv.named_parameters()
+    }
+}
+
+/// 2-D convolution over `in_channels` images stored one after another in
+/// row-major order. As a `Module` it needs the image size, set with
+/// [`Conv2d::input_size`]; [`Conv2d::eval`] takes it per call instead.
+#[derive(Debug)]
+pub struct Conv2d {
+    conv: Conv,
+    input_size: Option<(usize, usize)>,
+}
+
+impl Conv2d {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: (usize, usize)) -> Self {
+        Conv2d::with_init(
+            in_channels,
+            out_channels,
+            kernel_size,
+            true,
+            &Init::default(),
+        )
+    }
+
+    pub fn with_init(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: (usize, usize),
+        bias: bool,
+        init: &Init,
+    ) -> Self {
+        Conv2d {
+            conv: Conv::new(in_channels, out_channels, kernel_size, bias, init),
+            input_size: None,
+        }
+    }
+
+    pub fn stride(mut self, stride: (usize, usize)) -> Self {
+        self.conv.window.stride = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: (usize, usize)) -> Self {
+        self.conv.window.padding = padding;
+        self
+    }
+
+    pub fn dilation(mut self, dilation: (usize, usize)) -> Self {
+        self.conv.window.dilation = dilation;
+        self
+    }
+
+    /// Height and width of the input images, for use as a `Module`.
+    pub fn input_size(mut self, height: usize, width: usize) -> Self {
+        self.input_size = Some((height, width));
+        self
+    }
+
+    pub fn weights(&self) -> &[Vec<Unit>] {
+        &self.conv.weights
+    }
+
+    pub fn bias(&self) -> Option<&[Unit]> {
+        self.conv.bias.as_deref()
+    }
+
+    pub fn output_size(&self, height: usize, width: usize) -> (usize, usize) {
+        self.conv.window.output_size("Conv2d", (height, width))
+    }
+
+    pub fn eval(&self, input: &[Unit], height: usize, width: usize) -> Vec<Unit> {
+        self.conv.eval("Conv2d", input, (height, width))
+    }
+
+    fn size(&self) -> (usize, usize) {
+        self.input_size
+            .expect("Conv2d needs its input size; set it with Conv2d::input_size(height, width)")
+    }
+}
+
+impl Module for Conv2d {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        let (h, w) = self.size();
+        self.eval(input, h, w)
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        self.input_size.map(|(h, w)| self.conv.in_channels * h * w)
+    }
+
+    fn out_features(&self, _input: usize) -> usize {
+        let (h, w) = self.size();
+        let (oh, ow) = self.output_size(h, w);
+        self.conv.out_channels * oh * ow
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
+        self.conv.named_parameters()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::backward;
+
+    fn units(values: &[f32]) -> Vec<Unit> {
+        values.iter().map(|&v| new_unit(v)).collect()
+    }
+
+    fn set(params: &[Unit], values: &[f32]) {
+        for (p, &v) in params.iter().zip(values.iter()) {
+            p.borrow_mut().data = v;
+        }
+    }
+
+    #[test]
+    fn test_conv1d_stride_padding_dilation() {
+        let conv = Conv1d::with_init(1, 1, 2, false, &Init::Constant(1.0));
+        let x = units(&[1.0, 2.0, 3.0, 4.0, 5.0]);
+        let y = conv.forward(&x);
+        assert_eq!(
+            y.iter().map(|u| u.data()).collect::<Vec<_>>(),
+            vec![3.0, 5.0, 7.0, 9.0]
+        );
+
+        let conv = conv.stride(2).padding(1).dilation(2);
+        // padded: 0 1 2 3 4 5 0, taps two apart
+        let y = conv.forward(&x);
+        assert_eq!(
+            y.iter().map(|u| u.data()).collect::<Vec<_>>(),
+            vec![2.0, 6.0, 4.0]
+        );
+        assert_eq!(conv.out_features(5), 3);
+    }
+
+    #[test]
+    fn test_conv1d_channels_and_gradients() {
+        let conv = Conv1d::new(2, 3, 2);
+        assert_eq!(conv.num_parameters(), 3 * 2 * 2 + 3);
+        set(&conv.weights()[1], &[1.0, -1.0, 0.5, 2.0]);
+        set(conv.bias().unwrap(), &[0.0, 0.25, 0.0]);
+
+        // channel 0: 1 2 3, channel 1: 4 5 6
+        let x = units(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let y = conv.forward(&x);
+        assert_eq!(y.len(), 3 * 2);
+        // output channel 1, position 0: 1*1 - 1*2 + 0.5*4 + 2*5 + 0.25
+        assert_eq!(y[2].data(), 11.25);
+
+        backward(&y[3]);
+        let grads = x.iter().map(|u| u.grad()).collect::<Vec<_>>();
+        assert_eq!(grads, vec![0.0, 1.0, -1.0, 0.0, 0.5, 2.0]);
+        assert_eq!(conv.weights()[1][3].grad(), 6.0);
+        assert_eq!(conv.bias().unwrap()[1].grad(), 1.0);
+        assert_eq!(conv.weights()[0][0].grad(), 0.0);
+    }
+
+    #[test]
+    fn test_conv2d_padding_and_gradients() {
+        let conv = Conv2d::with_init(1, 1, (3, 3), false, &Init::Constant(1.0))
+            .padding((1, 1))
+            .input_size(3, 3);
+        assert_eq!(conv.in_features(), Some(9));
+        assert_eq!(conv.out_features(9), 9);
+
+        let x = units(&(1..=9).map(|v| v as f32).collect::<Vec<_>>());
+        let y = conv.forward(&x);
+        let values = y.iter().map(|u| u.data()).collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![12.0, 21.0, 16.0, 27.0, 45.0, 33.0, 24.0, 39.0, 28.0]
+        );
+
+        // the corner output sees the top-left 2x2 block of the input
+        backward(&y[0]);
+        let grads = x.iter().map(|u| u.grad()).collect::<Vec<_>>();
+        assert_eq!(grads, vec![1.0, 1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+        let w = conv.weights()[0]
+            .iter()
+            .map(|u| u.grad())
+            .collect::<Vec<_>>();
+        assert_eq!(w, vec![0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 0.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn test_conv2d_stride_output_size() {
+        let conv = Conv2d::new(3, 4, (3, 2)).stride((2, 1)).dilation((1, 2));
+        assert_eq!(conv.output_size(7, 5), (3, 3));
+        let y = conv.eval(&units(&[0.0; 3 * 7 * 5]), 7, 5);
+        assert_eq!(y.len(), 4 * 3 * 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Conv2d expects 2 channels of 4x4 = 32 inputs, got 16")]
+    fn test_conv2d_wrong_input_size() {
+        Conv2d::new(2, 1, (3, 3))
+            .input_size(4, 4)
+            .forward(&units(&[0.0; 16]));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Conv1d: input of size 2 (padded to 2) is smaller than the kernel span 3"
+    )]
+    fn test_conv1d_kernel_larger_than_input() {
+        Conv1d::new(1, 1, 3).forward(&units(&[1.0, 2.0]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Conv2d needs its input size")]
+    fn test_conv2d_without_input_size() {
+        Conv2d::new(1, 1, (2, 2)).forward(&units(&[0.0; 4]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Conv1d expects 2 channels of equal length, got 5 inputs")]
+    fn test_conv1d_uneven_channels() {
+        Conv1d::new(2, 1, 1).forward(&units(&[0.0; 5]));
+    }
+}
@@ -0,0 +1,139 @@
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::*;
+use crate::fundamental::op::mul;
+
+/// Zeroes each input with probability `p` while training and scales the
+/// survivors by `1 / (1 - p)`, so nothing needs rescaling at inference time.
+/// In eval mode inputs pass through unchanged.
+#[derive(Debug)]
+pub struct Dropout {
+    pub p: f32,
+    training: bool,
+    rng: RefCell<StdRng>,
+}
+
+impl Dropout {
+    pub fn new(p: f32) -> Self {
+        Dropout::from_rng(p, StdRng::from_entropy())
+    }
+
+    /// Draws its masks from a fixed seed, so runs are reproducible.
+    pub fn with_seed(p: f32, seed: u64) -> Self {
+        Dropout::from_rng(p, StdRng::seed_from_u64(seed))
+    }
+
+    fn from_rng(p: f32, rng: StdRng) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&p),
+            "dropout probability must be in [0, 1], got {}",
+            p
+        );
+        Dropout {
+            p,
+            training: true,
+            rng: RefCell::new(rng),
+        }
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+}
+
+impl Module for Dropout {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        if !self.training || self.p == 0.0 {
+            return input.to_vec();
+        }
+        let scale = if self.p < 1.0 {
+            1.0 / (1.0 - self.p)
+        } else {
+            0.0
+        };
+        let mut rng = self.rng.borrow_mut();
+        input
+            .iter()
+            .map(|x| {
+                let keep = rng.gen::<f32>() >= self.p;
+                // a constant: the mask itself needs no gradient
+                let mask = Unit::from(if keep { scale } else { 0.0 });
+                mask.set_requires_grad(false);
+                mul(x, &mask)
+            })
+            .collect()
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::backward;
+    use crate::fundamental::unit::new_unit;
+    use crate::nn::sequential::Sequential;
+
+    fn ones(n: usize) -> Vec<Unit> {
+        (0..n).map(|_| new_unit(1.0)).collect()
+    }
+
+    #[test]
+    fn test_dropout_scales_survivors() {
+        let d = Dropout::with_seed(0.25, 42);
+        let x = ones(4000);
+        let y = d.forward(&x);
+        let kept = y.iter().filter(|u| u.data() != 0.0).count();
+        assert!((2850..3150).contains(&kept), "kept {}", kept);
+        assert!(y
+            .iter()
+            .all(|u| u.data() == 0.0 || (u.data() - 4.0 / 3.0).abs() < 1e-6));
+
+        backward(&y[0]);
+        assert_eq!(x[0].grad(), y[0].data());
+        let mask = y[0].borrow().children[1].clone();
+        assert!(!mask.requires_grad());
+        assert_eq!(mask.grad(), 0.0);
+    }
+
+    #[test]
+    fn test_dropout_seed_and_eval_mode() {
+        let a = Dropout::with_seed(0.5, 7).forward(&ones(32));
+        let b = Dropout::with_seed(0.5, 7).forward(&ones(32));
+        assert!(a.iter().zip(b.iter()).all(|(x, y)| x.data() == y.data()));
+
+        let mut d = Dropout::new(0.9);
+        Module::eval(&mut d);
+        assert!(!d.is_training());
+        let x = ones(8);
+        assert_eq!(d.forward(&x), x);
+    }
+
+    #[test]
+    fn test_sequential_mode_reaches_dropout() {
+        let mut seq = Sequential::new()
+            .linear(3, 4)
+            .relu()
+            .dropout(1.0)
+            .linear(4, 1);
+        let x = vec![new_unit(1.0), new_unit(2.0), new_unit(3.0)];
+        // everything is dropped in training mode, so only the bias is left
+        let bias = seq.named_parameters().last().unwrap().1.data();
+        assert_eq!(seq.forward(&x)[0].data(), bias);
+
+        Module::eval(&mut seq);
+        let mut expected = x.clone();
+        for i in [0, 1, 3] {
+            expected = seq.modules[i].forward(&expected);
+        }
+        assert_eq!(seq.forward(&x)[0].data(), expected[0].data());
+
+        seq.train();
+        assert_eq!(seq.forward(&x)[0].data(), bias);
+    }
+}
@@ -0,0 +1,174 @@
+use super::*;
+use crate::fundamental::unit::new_unit;
+use crate::nn::init::{standard_normal, Fan, Init};
+
+/// A learnable lookup table from token ids to vectors of `dim` values. The
+/// rows themselves are returned, so a backward pass only reaches the rows that
+/// were looked up, once per occurrence.
+#[derive(Debug)]
+pub struct Embedding {
+    pub num_embeddings: usize,
+    pub dim: usize,
+    /// one row per id
+    pub weights: Vec<Vec<Unit>>,
+}
+
+impl Embedding {
+    /// Rows drawn from a standard normal distribution.
+    pub fn new(num_embeddings: usize, dim: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        let weights = (0..num_embeddings)
+            .map(|_| {
+                (0..dim)
+                    .map(|_| new_unit(standard_normal(&mut rng)))
+                    .collect()
+            })
+            .collect();
+        Embedding {
+            num_embeddings,
+            dim,
+            weights,
+        }
+    }
+
+    pub fn with_init(num_embeddings: usize, dim: usize, init: &Init) -> Self {
+        let fan = Fan {
+            fan_in: dim,
+            fan_out: num_embeddings,
+        };
+        let weights = init
+            .sample(fan)
+            .into_iter()
+            .map(|row| row.into_iter().map(new_unit).collect())
+            .collect();
+        Embedding {
+            num_embeddings,
+            dim,
+            weights,
+        }
+    }
+
+    pub fn lookup(&self, id: usize) -> &[Unit] {
+        assert!(
+            id < self.num_embeddings,
+            "id {} is out of range for an Embedding of {} rows",
+            id,
+            self.num_embeddings
+        );
+        &self.weights[id]
+    }
+
+    /// The vectors of `ids` one after another, `ids.len() * dim` values in
+    /// all, ready to feed a context window into an `MLP`.
+    pub fn embed(&self, ids: &[usize]) -> Vec<Unit> {
+        ids.iter()
+            .flat_map(|&id| self.lookup(id).iter().cloned())
+            .collect()
+    }
+}
+
+impl Module for Embedding {
+    /// Reads each input as a token id, so an `Embedding` can lead a
+    /// `Sequential`. Inputs must hold non-negative whole numbers.
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        let ids = input
+            .iter()
+            .map(|u| {
+                let v = u.data();
+                assert!(
+                    v >= 0.0 && v.fract() == 0.0,
+                    "Embedding expects token ids, got {}",
+                    v
+                );
+                v as usize
+            })
+            .collect::<Vec<_>>();
+        self.embed(&ids)
+    }
+
+    fn out_features(&self, input: usize) -> usize {
+        input * self.dim
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
+        let mut params = Vec::new();
+        for (id, row) in self.weights.iter().enumerate() {
+            for (j, w) in row.iter().enumerate() {
+                params.push((format!("weights.{}.{}", id, j), w.clone()));
+            }
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::{add, backward};
+    use crate::nn::init::reseed;
+    use crate::nn::loss::{cross_entropy, Reduction};
+    use crate::nn::mlp::MLP;
+    use crate::nn::sequential::Sequential;
+
+    #[test]
+    fn test_gradients_reach_only_looked_up_rows() {
+        let emb = Embedding::with_init(4, 2, &Init::Constant(0.5));
+        assert_eq!(emb.num_parameters(), 8);
+        assert_eq!(emb.named_parameters()[3].0, "weights.1.1");
+
+        let x = emb.embed(&[2, 0, 2]);
+        assert_eq!(x.len(), 6);
+        let total = x[1..].iter().fold(x[0].clone(), |acc, u| add(&acc, u));
+        backward(&total);
+
+        let grads = emb
+            .weights
+            .iter()
+            .map(|row| row.iter().map(|w| w.grad()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            grads,
+            vec![vec![1.0, 1.0], vec![0.0; 2], vec![2.0, 2.0], vec![0.0; 2]]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "id 5 is out of range for an Embedding of 3 rows")]
+    fn test_out_of_range_id() {
+        Embedding::new(3, 2).embed(&[5]);
+    }
+
+    /// Bigram model: the embedding of the current char feeds an `MLP` that
+    /// predicts the next one.
+    #[test]
+    fn test_embedding_feeds_mlp() {
+        let text = [0usize, 1, 2, 0, 1, 2, 0, 1, 2];
+        let emb = Embedding::new(3, 4);
+        let mlp = MLP::new(4, vec![8], 3);
+        reseed(&emb, 1.0, 1);
+        reseed(&mlp, 1.0, 2);
+        let loss = |model: (&Embedding, &MLP)| {
+            let logits = text
+                .windows(2)
+                .map(|w| model.1.eval(model.0.embed(&w[..1])))
+                .collect::<Vec<_>>();
+            let targets = text.windows(2).map(|w| w[1]).collect::<Vec<_>>();
+            cross_entropy(&logits, &targets, Reduction::Mean).remove(0)
+        };
+
+        let mut params = emb.parameters();
+        params.extend(mlp.parameters());
+        let first = loss((&emb, &mlp)).data();
+        for _ in 0..50 {
+            params.iter().for_each(|p| p.zero_grad());
+            backward(&loss((&emb, &mlp)));
+            params.iter().for_each(|p| p.adjust(-0.1));
+        }
+        assert!(loss((&emb, &mlp)).data() < first / 2.0);
+
+        // the same pipeline as a `Sequential`, fed ids as units
+        let seq = Sequential::new().then(emb).then(mlp);
+        assert_eq!(seq.forward(&[new_unit(1.0)]).len(), 3);
+        assert!(seq.named_parameters()[0].0.starts_with("0.weights."));
+    }
+}
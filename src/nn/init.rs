@@ -0,0 +1,298 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::rc::Rc;
+
+use rand::distributions::{Distribution, Uniform};
+use rand::{Rng, RngCore};
+
+/// Number of inputs and outputs of the layer whose weights are being drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fan {
+    pub fan_in: usize,
+    pub fan_out: usize,
+}
+
+/// Draws one weight given the fan of its layer.
+pub type InitFn = Rc<dyn Fn(Fan, &mut dyn RngCore) -> f32>;
+
+/// How the weights of a layer are drawn. Biases always start at zero.
+#[derive(Clone)]
+pub enum Init {
+    /// Uniform(low, high), independent of the fan.
+    Uniform(f32, f32),
+    /// Glorot: Uniform(-a, a) with a = sqrt(6 / (fan_in + fan_out)).
+    XavierUniform,
+    /// Glorot: Normal(0, 2 / (fan_in + fan_out)).
+    XavierNormal,
+    /// He: Uniform(-a, a) with a = sqrt(6 / fan_in), for ReLU layers.
+    KaimingUniform,
+    /// He: Normal(0, 2 / fan_in), for ReLU layers.
+    KaimingNormal,
+    /// Uniform(-a, a) with a = sqrt(3 / fan_in), for SELU/tanh layers.
+    LeCunUniform,
+    /// Normal(0, 1 / fan_in).
+    LeCunNormal,
+    Zeros,
+    Constant(f32),
+    /// Rows (or columns, whichever are fewer) form an orthonormal set, scaled by the gain.
+    Orthogonal(f32),
+    /// Draws each weight from the closure.
+    Custom(InitFn),
+}
+
+impl Default for Init {
+    /// Uniform(-1, 1), the historical behaviour of `Neuron::new`.
+    fn default() -> Self {
+        Init::Uniform(-1.0, 1.0)
+    }
+}
+
+impl fmt::Debug for Init {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Init::Uniform(low, high) => write!(f, "Uniform({}, {})", low, high),
+            Init::XavierUniform => write!(f, "XavierUniform"),
+            Init::XavierNormal => write!(f, "XavierNormal"),
+            Init::KaimingUniform => write!(f, "KaimingUniform"),
+            Init::KaimingNormal => write!(f, "KaimingNormal"),
+            Init::LeCunUniform => write!(f, "LeCunUniform"),
+            Init::LeCunNormal => write!(f, "LeCunNormal"),
+            Init::Zeros => write!(f, "Zeros"),
+            Init::Constant(c) => write!(f, "Constant({})", c),
+            Init::Orthogonal(gain) => write!(f, "Orthogonal({})", gain),
+            Init::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// Standard normal sample by Box-Muller.
+pub(crate) fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f32 {
+    let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+impl Init {
+    /// Weights for a layer of `fan.fan_out` neurons with `fan.fan_in` inputs
+    /// each, one row per neuron.
+    pub fn sample(&self, fan: Fan) -> Vec<Vec<f32>> {
+        self.sample_with(fan, &mut rand::thread_rng())
+    }
+
+    /// Like [`Init::sample`], drawing from `rng` so the result is reproducible.
+    pub fn sample_with<R: Rng>(&self, fan: Fan, rng: &mut R) -> Vec<Vec<f32>> {
+        self.sample_rows_with(fan.fan_out, fan, rng)
+    }
+
+    /// `rows` rows of `fan.fan_in` weights, scaled for `fan`. A convolution
+    /// has one row per output channel, but its fan out also counts the
+    /// kernel taps each output channel reaches.
+    pub fn sample_rows(&self, rows: usize, fan: Fan) -> Vec<Vec<f32>> {
+        self.sample_rows_with(rows, fan, &mut rand::thread_rng())
+    }
+
+    /// Like [`Init::sample_rows`], drawing from `rng`.
+    pub fn sample_rows_with<R: Rng>(&self, rows: usize, fan: Fan, rng: &mut R) -> Vec<Vec<f32>> {
+        let (fan_in, fan_out) = (fan.fan_in as f32, fan.fan_out as f32);
+        let cols = fan.fan_in;
+        let uniform = |a: f32, rng: &mut R| {
+            let between = Uniform::from(-a..=a);
+            fill(rows, cols, || between.sample(rng))
+        };
+        let normal = |std: f32, rng: &mut R| fill(rows, cols, || std * standard_normal(rng));
+
+        match self {
+            Init::Uniform(low, high) => {
+                assert!(
+                    low < high,
+                    "Init::Uniform needs low < high, got ({}, {})",
+                    low,
+                    high
+                );
+                let between = Uniform::from(*low..*high);
+                fill(rows, cols, || between.sample(rng))
+            }
+            Init::XavierUniform => uniform((6.0 / (fan_in + fan_out)).sqrt(), rng),
+            Init::XavierNormal => normal((2.0 / (fan_in + fan_out)).sqrt(), rng),
+            Init::KaimingUniform => uniform((6.0 / fan_in).sqrt(), rng),
+            Init::KaimingNormal => normal((2.0 / fan_in).sqrt(), rng),
+            Init::LeCunUniform => uniform((3.0 / fan_in).sqrt(), rng),
+            Init::LeCunNormal => normal((1.0 / fan_in).sqrt(), rng),
+            Init::Zeros => fill(rows, cols, || 0.0),
+            Init::Constant(c) => fill(rows, cols, || *c),
+            Init::Orthogonal(gain) => orthogonal(rows, cols, *gain, rng),
+            Init::Custom(f) => fill(rows, cols, || f(fan, rng)),
+        }
+    }
+}
+
+fn fill<F: FnMut() -> f32>(rows: usize, cols: usize, mut f: F) -> Vec<Vec<f32>> {
+    (0..rows)
+        .map(|_| (0..cols).map(|_| f()).collect())
+        .collect()
+}
+
+/// Gram-Schmidt over a Gaussian matrix. With more rows than columns the
+/// columns are made orthonormal instead, by working on the transpose.
+fn orthogonal<R: Rng>(rows: usize, cols: usize, gain: f32, rng: &mut R) -> Vec<Vec<f32>> {
+    let transposed = rows > cols;
+    let (rows, cols) = if transposed {
+        (cols, rows)
+    } else {
+        (rows, cols)
+    };
+
+    let mut q: Vec<Vec<f32>> = Vec::with_capacity(rows);
+    while q.len() < rows {
+        let mut v = (0..cols).map(|_| standard_normal(rng)).collect::<Vec<_>>();
+        for u in q.iter() {
+            let dot = v.iter().zip(u.iter()).map(|(a, b)| a * b).sum::<f32>();
+            v.iter_mut().zip(u.iter()).for_each(|(a, b)| *a -= dot * b);
+        }
+        let norm = v.iter().map(|a| a * a).sum::<f32>().sqrt();
+        // a nearly dependent draw is discarded and drawn again
+        if norm > 1e-3 {
+            v.iter_mut().for_each(|a| *a /= norm);
+            q.push(v);
+        }
+    }
+
+    let scale = |q: Vec<Vec<f32>>| -> Vec<Vec<f32>> {
+        q.into_iter()
+            .map(|row| row.into_iter().map(|a| a * gain).collect())
+            .collect()
+    };
+    if transposed {
+        scale(
+            (0..cols)
+                .map(|j| (0..rows).map(|i| q[i][j]).collect())
+                .collect(),
+        )
+    } else {
+        scale(q)
+    }
+}
+
+/// Redraws every parameter of `module` from Uniform(-bound, bound) with a
+/// fixed seed, so tests that train a model behave the same on every run.
+#[cfg(test)]
+pub(crate) fn reseed<M: crate::nn::Module + ?Sized>(module: &M, bound: f32, seed: u64) {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let params = module.parameters();
+    let fan = Fan {
+        fan_in: params.len(),
+        fan_out: 1,
+    };
+    let values = Init::Uniform(-bound, bound).sample_with(fan, &mut StdRng::seed_from_u64(seed));
+    for (p, v) in params.iter().zip(values[0].iter()) {
+        p.borrow_mut().data = *v;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn dot(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn test_bounds_follow_fan() {
+        let fan = Fan {
+            fan_in: 50,
+            fan_out: 10,
+        };
+        let w = Init::XavierUniform.sample(fan);
+        assert_eq!(w.len(), 10);
+        assert_eq!(w[0].len(), 50);
+        let bound = (6.0f32 / 60.0).sqrt();
+        assert!(w.iter().flatten().all(|v| v.abs() <= bound));
+
+        let w = Init::KaimingNormal.sample_with(
+            Fan {
+                fan_in: 400,
+                fan_out: 100,
+            },
+            &mut StdRng::seed_from_u64(7),
+        );
+        let n = 40_000.0;
+        let var = w.iter().flatten().map(|v| v * v).sum::<f32>() / n;
+        assert!((var - 2.0 / 400.0).abs() < 0.0015, "variance {}", var);
+    }
+
+    #[test]
+    fn test_orthogonal_rows_and_columns() {
+        for &(fan_in, fan_out) in [(6, 4), (3, 5)].iter() {
+            let w = Init::Orthogonal(1.0).sample(Fan { fan_in, fan_out });
+            if fan_out <= fan_in {
+                for i in 0..fan_out {
+                    for j in 0..fan_out {
+                        let expected = if i == j { 1.0 } else { 0.0 };
+                        assert!((dot(&w[i], &w[j]) - expected).abs() < 1e-4);
+                    }
+                }
+            } else {
+                let col = |j: usize| w.iter().map(|r| r[j]).collect::<Vec<_>>();
+                for i in 0..fan_in {
+                    for j in 0..fan_in {
+                        let expected = if i == j { 1.0 } else { 0.0 };
+                        assert!((dot(&col(i), &col(j)) - expected).abs() < 1e-4);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_rows_keeps_scale_of_fan() {
+        // a 3x3 convolution from 2 to 4 channels
+        let fan = Fan {
+            fan_in: 18,
+            fan_out: 36,
+        };
+        let w = Init::Orthogonal(1.0).sample_rows(4, fan);
+        assert_eq!((w.len(), w[0].len()), (4, 18));
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((dot(&w[i], &w[j]) - expected).abs() < 1e-4);
+            }
+        }
+        let w = Init::XavierUniform.sample_rows(4, fan);
+        let bound = (6.0f32 / 54.0).sqrt();
+        assert!(w.iter().flatten().all(|v| v.abs() <= bound));
+    }
+
+    #[test]
+    #[should_panic(expected = "Init::Uniform needs low < high, got (1, 1)")]
+    fn test_uniform_rejects_empty_range() {
+        Init::Uniform(1.0, 1.0).sample(Fan {
+            fan_in: 2,
+            fan_out: 2,
+        });
+    }
+
+    #[test]
+    fn test_custom_sees_fan() {
+        let init = Init::Custom(Rc::new(|fan: Fan, _: &mut dyn RngCore| {
+            fan.fan_in as f32 * 10.0 + fan.fan_out as f32
+        }));
+        let w = init.sample(Fan {
+            fan_in: 3,
+            fan_out: 2,
+        });
+        assert_eq!(w, vec![vec![32.0; 3]; 2]);
+        assert_eq!(
+            Init::Constant(0.5).sample(Fan {
+                fan_in: 1,
+                fan_out: 1
+            }),
+            vec![vec![0.5]]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use super::*;
+use crate::fundamental::op::{add, dot};
+use crate::fundamental::unit::new_unit;
+use crate::fundamental::Unit;
+use crate::nn::init::{Fan, Init};
+
+/// Fully connected layer `y = W x + b` without an activation. Each output is
+/// one `dot` node plus the bias, rather than a `mul` and an `add` per input.
+#[derive(Debug)]
+pub struct Linear {
+    pub in_features: usize,
+    pub out_features: usize,
+    /// one row of `in_features` weights per output
+    pub weights: Vec<Vec<Unit>>,
+    pub bias: Option<Vec<Unit>>,
+}
+
+impl Linear {
+    pub fn new(in_features: usize, out_features: usize, bias: bool) -> Self {
+        Linear::with_init(in_features, out_features, bias, &Init::default())
+    }
+
+    pub fn with_init(in_features: usize, out_features: usize, bias: bool, init: &Init) -> Self {
+        let fan = Fan {
+            fan_in: in_features,
+            fan_out: out_features,
+        };
+        let weights = init
+            .sample(fan)
+            .into_iter()
+            .map(|row| row.into_iter().map(new_unit).collect())
+            .collect();
+        let bias = if bias {
+            Some((0..out_features).map(|_| new_unit(0.0)).collect())
+        } else {
+            None
+        };
+        Linear {
+            in_features,
+            out_features,
+            weights,
+            bias,
+        }
+    }
+
+    pub fn eval(&self, input: &[Unit]) -> Vec<Unit> {
+        assert_eq!(
+            input.len(),
+            self.in_features,
+            "Linear expects {} inputs, got {}",
+            self.in_features,
+            input.len()
+        );
+        let mut output = Vec::with_capacity(self.out_features);
+        for (o, row) in self.weights.iter().enumerate() {
+            let y = dot(row, input);
+            output.push(match self.bias {
+                Some(ref b) => add(&y, &b[o]),
+                None => y,
+            });
+        }
+        output
+    }
+}
+
+impl Module for Linear {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        self.eval(input)
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        Some(self.in_features)
+    }
+
+    fn out_features(&self, _input: usize) -> usize {
+        self.out_features
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
+        let mut params = Vec::new();
+        for (o, row) in self.weights.iter().enumerate() {
+            for (i, w) in row.iter().enumerate() {
+                params.push((format!("weights.{}.{}", o, i), w.clone()));
+            }
+        }
+        if let Some(ref b) = self.bias {
+            for (o, u) in b.iter().enumerate() {
+                params.push((format!("bias.{}", o), u.clone()));
+            }
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::backward;
+
+    #[test]
+    fn test_linear_eval_and_backward() {
+        let l = Linear::with_init(3, 2, true, &Init::Constant(0.5));
+        assert_eq!(l.parameters().len(), 3 * 2 + 2);
+        l.bias.as_ref().unwrap()[1].borrow_mut().data = 1.0;
+
+        let x = vec![new_unit(1.0), new_unit(2.0), new_unit(3.0)];
+        let y = l.eval(&x);
+        assert_eq!(y[0].data(), 3.0);
+        assert_eq!(y[1].data(), 4.0);
+
+        backward(&y[1]);
+        assert!(l.weights[1]
+            .iter()
+            .zip(x.iter())
+            .all(|(w, x)| w.grad() == x.data()));
+        assert!(l.weights[0].iter().all(|w| w.grad() == 0.0));
+        assert!(x.iter().all(|x| x.grad() == 0.5));
+        assert_eq!(l.bias.as_ref().unwrap()[1].grad(), 1.0);
+    }
+
+    #[test]
+    fn test_linear_without_bias() {
+        let l = Linear::new(4, 3, false);
+        assert!(l.bias.is_none());
+        assert_eq!(l.parameters().len(), 12);
+        let y = l.eval(&[new_unit(0.0), new_unit(0.0), new_unit(0.0), new_unit(0.0)]);
+        assert!(y.iter().all(|v| v.data() == 0.0));
+    }
+}
@@ -0,0 +1,348 @@
+use crate::fundamental::op::*;
+use crate::fundamental::Unit;
+
+/// How per-element losses are combined. `Mean` and `Sum` return a single
+/// unit, `None` returns one loss per element (or per sample for the
+/// classification losses).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reduction {
+    #[default]
+    Mean,
+    Sum,
+    None,
+}
+
+impl Reduction {
+    pub fn reduce(&self, losses: Vec<Unit>) -> Vec<Unit> {
+        match self {
+            Reduction::None => losses,
+            Reduction::Sum => vec![total(&losses)],
+            Reduction::Mean => {
+                assert!(!losses.is_empty(), "the mean of no losses is undefined");
+                let n = Unit::from(1.0 / losses.len() as f32);
+                vec![mul(&total(&losses), &n)]
+            }
+        }
+    }
+}
+
+fn total(xs: &[Unit]) -> Unit {
+    xs.iter().fold(Unit::from(0.0), |acc, x| add(&acc, x))
+}
+
+fn check_len(pred: usize, target: usize, loss: &str) {
+    assert_eq!(
+        pred, target,
+        "{} got {} predictions and {} targets",
+        loss, pred, target
+    );
+}
+
+/// `ln(x)`, floored at -100 the way PyTorch does it, so a probability of
+/// exactly 0 or 1 still gives a finite loss.
+fn clamped_log(x: &Unit) -> Unit {
+    if x.data() > (-100.0f32).exp() {
+        log(x)
+    } else {
+        Unit::from(-100.0)
+    }
+}
+
+/// Mean squared error `(pred - target)^2`.
+pub fn mse(pred: &[Unit], target: &[Unit], reduction: Reduction) -> Vec<Unit> {
+    check_len(pred.len(), target.len(), "mse");
+    let losses = pred
+        .iter()
+        .zip(target.iter())
+        .map(|(p, t)| pow(&(p - t), 2.0))
+        .collect();
+    reduction.reduce(losses)
+}
+
+/// Mean absolute error `|pred - target|`.
+pub fn mae(pred: &[Unit], target: &[Unit], reduction: Reduction) -> Vec<Unit> {
+    check_len(pred.len(), target.len(), "mae");
+    let losses = pred
+        .iter()
+        .zip(target.iter())
+        .map(|(p, t)| abs(&(p - t)))
+        .collect();
+    reduction.reduce(losses)
+}
+
+/// Quadratic within `delta` of the target and linear beyond it:
+/// `0.5 d^2` if `|d| <= delta`, else `delta (|d| - 0.5 delta)`.
+pub fn huber(pred: &[Unit], target: &[Unit], delta: f32, reduction: Reduction) -> Vec<Unit> {
+    check_len(pred.len(), target.len(), "huber");
+    let losses = pred
+        .iter()
+        .zip(target.iter())
+        .map(|(p, t)| {
+            let d = p - t;
+            if d.data().abs() <= delta {
+                mul(&pow(&d, 2.0), &Unit::from(0.5))
+            } else {
+                mul(&(&abs(&d) - &Unit::from(0.5 * delta)), &Unit::from(delta))
+            }
+        })
+        .collect();
+    reduction.reduce(losses)
+}
+
+/// Huber loss divided by `beta`, so the linear part has slope 1. A `beta`
+/// of 0 is plain L1, as in PyTorch.
+pub fn smooth_l1(pred: &[Unit], target: &[Unit], beta: f32, reduction: Reduction) -> Vec<Unit> {
+    assert!(beta >= 0.0, "smooth_l1 needs beta >= 0, got {}", beta);
+    if beta == 0.0 {
+        return mae(pred, target, reduction);
+    }
+    let scale = Unit::from(1.0 / beta);
+    let losses = huber(pred, target, beta, Reduction::None)
+        .iter()
+        .map(|l| mul(l, &scale))
+        .collect();
+    reduction.reduce(losses)
+}
+
+/// Binary cross-entropy of probabilities `pred` in [0, 1] against targets in [0, 1].
+pub fn binary_cross_entropy(pred: &[Unit], target: &[Unit], reduction: Reduction) -> Vec<Unit> {
+    check_len(pred.len(), target.len(), "binary_cross_entropy");
+    let one = Unit::from(1.0);
+    let losses = pred
+        .iter()
+        .zip(target.iter())
+        .map(|(p, t)| {
+            let pos = mul(t, &clamped_log(p));
+            let neg = mul(&(&one - t), &clamped_log(&(&one - p)));
+            -(pos + neg)
+        })
+        .collect();
+    reduction.reduce(losses)
+}
+
+/// Binary cross-entropy on raw scores, as
+/// `max(x, 0) - x * y + log(1 + exp(-|x|))`, which never overflows.
+pub fn binary_cross_entropy_with_logits(
+    logits: &[Unit],
+    target: &[Unit],
+    reduction: Reduction,
+) -> Vec<Unit> {
+    check_len(
+        logits.len(),
+        target.len(),
+        "binary_cross_entropy_with_logits",
+    );
+    let one = Unit::from(1.0);
+    let losses = logits
+        .iter()
+        .zip(target.iter())
+        .map(|(x, y)| {
+            // branch on the sign so the exponent is never positive, keeping the
+            // slope sigmoid(x) - y exact on both sides
+            if x.data() >= 0.0 {
+                let softplus = log(&(&one + &exp(&-x)));
+                &(x - &mul(x, y)) + &softplus
+            } else {
+                let softplus = log(&(&one + &exp(x)));
+                &softplus - &mul(x, y)
+            }
+        })
+        .collect();
+    reduction.reduce(losses)
+}
+
+/// Cross-entropy of one vector of class scores per sample against the index
+/// of the true class, i.e. `nll` of their `log_softmax`.
+pub fn cross_entropy(logits: &[Vec<Unit>], targets: &[usize], reduction: Reduction) -> Vec<Unit> {
+    check_len(logits.len(), targets.len(), "cross_entropy");
+    let log_probs = logits.iter().map(|x| log_softmax(x)).collect::<Vec<_>>();
+    nll(&log_probs, targets, reduction)
+}
+
+/// Negative log-likelihood of log-probabilities, e.g. the output of a
+/// log-softmax, against the index of the true class.
+pub fn nll(log_probs: &[Vec<Unit>], targets: &[usize], reduction: Reduction) -> Vec<Unit> {
+    check_len(log_probs.len(), targets.len(), "nll");
+    let losses = log_probs
+        .iter()
+        .zip(targets.iter())
+        .map(|(x, &t)| {
+            assert!(t < x.len(), "target class {} of {} classes", t, x.len());
+            -&x[t]
+        })
+        .collect();
+    reduction.reduce(losses)
+}
+
+/// Max-margin loss `max(0, 1 - y * score)` for labels `y` of -1 or 1, as in
+/// micrograd's moons demo.
+pub fn hinge(scores: &[Unit], target: &[Unit], reduction: Reduction) -> Vec<Unit> {
+    check_len(scores.len(), target.len(), "hinge");
+    let one = Unit::from(1.0);
+    let losses = scores
+        .iter()
+        .zip(target.iter())
+        .map(|(s, y)| relu(&(&one - &mul(y, s))))
+        .collect();
+    reduction.reduce(losses)
+}
+
+/// KL divergence `target * (log(target) - input)` where `input` holds
+/// log-probabilities and `target` probabilities. Terms with a zero target are 0.
+pub fn kl_div(input: &[Unit], target: &[Unit], reduction: Reduction) -> Vec<Unit> {
+    check_len(input.len(), target.len(), "kl_div");
+    let losses = input
+        .iter()
+        .zip(target.iter())
+        .map(|(q, p)| {
+            if p.data() > 0.0 {
+                mul(p, &(&log(p) - q))
+            } else {
+                Unit::from(0.0)
+            }
+        })
+        .collect();
+    reduction.reduce(losses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::unit::new_unit;
+
+    fn units(xs: &[f32]) -> Vec<Unit> {
+        xs.iter().map(|&x| new_unit(x)).collect()
+    }
+
+    fn close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} vs {}", a, b);
+    }
+
+    #[test]
+    fn test_regression_losses_and_reductions() {
+        let p = units(&[1.0, 2.0, 5.0]);
+        let t = units(&[1.5, 2.0, 2.0]);
+        close(mse(&p, &t, Reduction::Mean)[0].data(), (0.25 + 9.0) / 3.0);
+        close(mse(&p, &t, Reduction::Sum)[0].data(), 9.25);
+        assert_eq!(mse(&p, &t, Reduction::None).len(), 3);
+        close(mae(&p, &t, Reduction::Sum)[0].data(), 3.5);
+
+        let h = huber(&p, &t, 1.0, Reduction::None);
+        close(h[0].data(), 0.125);
+        close(h[2].data(), 2.5);
+        let s = smooth_l1(&p, &t, 2.0, Reduction::None);
+        close(s[0].data(), 0.0625);
+        close(s[2].data(), 2.0);
+
+        backward(&h[2]);
+        assert_eq!(p[2].grad(), 1.0);
+        assert_eq!(t[2].grad(), -1.0);
+    }
+
+    #[test]
+    fn test_smooth_l1_with_zero_beta_is_l1() {
+        let pred = vec![new_unit(0.2), new_unit(3.0)];
+        let target = vec![new_unit(0.0), new_unit(1.0)];
+        let loss = smooth_l1(&pred, &target, 0.0, Reduction::Mean)[0].data();
+        assert!((loss - 1.1).abs() < 1e-6, "{}", loss);
+    }
+
+    #[test]
+    #[should_panic(expected = "the mean of no losses is undefined")]
+    fn test_mean_of_empty_input_panics() {
+        mse(&[], &[], Reduction::Mean);
+    }
+
+    #[test]
+    fn test_binary_losses_are_stable() {
+        let logits = units(&[100.0, -100.0, 0.0]);
+        let y = units(&[0.0, 1.0, 1.0]);
+        let l = binary_cross_entropy_with_logits(&logits, &y, Reduction::None);
+        close(l[0].data(), 100.0);
+        close(l[1].data(), 100.0);
+        close(l[2].data(), 2.0f32.ln());
+        backward(&l[2]);
+        // d/dx = sigmoid(x) - y
+        close(logits[2].grad(), -0.5);
+
+        let p = units(&[0.0, 0.8]);
+        let y = units(&[1.0, 1.0]);
+        let l = binary_cross_entropy(&p, &y, Reduction::None);
+        close(l[0].data(), 100.0);
+        close(l[1].data(), -(0.8f32.ln()));
+    }
+
+    #[test]
+    fn test_cross_entropy_gradient_is_softmax_minus_onehot() {
+        let logits = vec![units(&[1000.0, 0.0, -1000.0]), units(&[1.0, 2.0, 3.0])];
+        let l = cross_entropy(&logits, &[1, 2], Reduction::None);
+        close(l[0].data(), 1000.0);
+        let denom = 1.0f32.exp() + 2.0f32.exp() + 3.0f32.exp();
+        close(l[1].data(), denom.ln() - 3.0);
+
+        backward(&l[1]);
+        let softmax = [1.0f32, 2.0, 3.0].map(|v| v.exp() / denom);
+        close(logits[1][0].grad(), softmax[0]);
+        close(logits[1][1].grad(), softmax[1]);
+        close(logits[1][2].grad(), softmax[2] - 1.0);
+
+        let log_probs = vec![units(&[-0.1, -2.0]), units(&[-3.0, -0.05])];
+        close(nll(&log_probs, &[0, 1], Reduction::Mean)[0].data(), 0.075);
+    }
+
+    #[test]
+    fn test_train_classifier_on_layer_outputs() {
+        use crate::nn::activation::Activation;
+        use crate::nn::mlp::MLP;
+        use crate::nn::Module;
+
+        let mlp =
+            MLP::with_activations(2, vec![8, 3], vec![Activation::Tanh, Activation::Identity]);
+        let xs = [
+            [1.0, 0.0],
+            [0.0, 1.0],
+            [-1.0, -1.0],
+            [0.9, 0.1],
+            [0.1, 0.8],
+            [-0.8, -1.0],
+        ];
+        let ys = [0, 1, 2, 0, 1, 2];
+
+        let mut losses = Vec::new();
+        for _ in 0..40 {
+            let logits = xs
+                .iter()
+                .map(|x| mlp.forward(&units(x)))
+                .collect::<Vec<_>>();
+            let loss = cross_entropy(&logits, &ys, Reduction::Mean).pop().unwrap();
+            for p in mlp.parameters() {
+                p.zero_grad();
+            }
+            backward(&loss);
+            for p in mlp.parameters() {
+                let mut p = p.borrow_mut();
+                p.data -= 0.1 * p.grad;
+            }
+            losses.push(loss.data());
+        }
+        assert!(losses.last().unwrap() < &(losses[0] * 0.5), "{:?}", losses);
+    }
+
+    #[test]
+    fn test_hinge_and_kl() {
+        let scores = units(&[2.0, 0.5, -0.3]);
+        let y = units(&[1.0, 1.0, 1.0]);
+        let l = hinge(&scores, &y, Reduction::None);
+        assert_eq!(
+            l.iter().map(|u| u.data()).collect::<Vec<_>>(),
+            vec![0.0, 0.5, 1.3]
+        );
+
+        let q = units(&[0.25f32.ln(), 0.75f32.ln()]);
+        let p = units(&[0.5, 0.5]);
+        let expected = 0.5 * (0.5f32 / 0.25).ln() + 0.5 * (0.5f32 / 0.75).ln();
+        close(kl_div(&q, &p, Reduction::Sum)[0].data(), expected);
+        let zero = kl_div(&q, &units(&[0.0, 1.0]), Reduction::None);
+        assert_eq!(zero[0].data(), 0.0);
+    }
+}
@@ -1,54 +1,85 @@
-use std::fmt;
 use super::*;
 use crate::fundamental::op::*;
 use crate::fundamental::unit::new_unit;
 use crate::fundamental::Unit;
-use rand::distributions::{Distribution, Uniform};
+use crate::nn::activation::Activation;
+use crate::nn::init::{Fan, Init};
+use std::fmt;
 use std::iter::zip;
 
 #[derive(Debug)]
 pub struct Neuron {
     pub weights: Vec<Unit>,
     pub bias: Unit,
-    pub activation: bool,
+    pub activation: Activation,
 }
 
 impl Neuron {
-    pub fn new(input: u32, activation: bool) -> Self {
-        let mut rng = rand::thread_rng();
-        let between: Uniform<f32> = Uniform::from(-1.0..1.0);
-        let mut weights = Vec::with_capacity(input as usize);
-        for _ in 0..input {
-            weights.push(new_unit(between.sample(&mut rng)));
-        }
+    pub fn new(input: u32, activation: Activation) -> Self {
+        Neuron::with_init(input, activation, &Init::default())
+    }
+
+    /// A lone neuron is drawn as a layer with a fan-out of one.
+    pub fn with_init(input: u32, activation: Activation, init: &Init) -> Self {
+        let fan = Fan {
+            fan_in: input as usize,
+            fan_out: 1,
+        };
+        let weights = init.sample(fan).pop().unwrap();
+        Neuron::from_weights(weights, 0.0, activation)
+    }
 
+    pub fn from_weights(weights: Vec<f32>, bias: f32, activation: Activation) -> Self {
         Neuron {
-            weights,
-            bias: new_unit(0.0),
+            weights: weights.into_iter().map(new_unit).collect(),
+            bias: new_unit(bias),
             activation,
         }
     }
 
-    pub fn eval(&self, input: &Vec<Unit>) -> Unit {
+    pub fn eval(&self, input: &[Unit]) -> Unit {
         let mut sum = self.bias.clone();
         for (i, w) in zip(input.iter(), self.weights.iter()) {
             let n = mul(i, w);
             sum = add(&sum, &n);
         }
-        if self.activation {
-            sum = relu(&sum);
-        }
-        sum
+        self.activation.apply(&sum)
     }
 }
 
-impl Zeroable for Neuron {
-    fn parameters(&self) -> Vec<Unit> {
+/// `eval` zips inputs with weights and would silently drop extra inputs, so
+/// the `Module` entry points check the length as `Linear` does.
+fn check_input(name: &str, expected: usize, input: &[Unit]) {
+    assert_eq!(
+        input.len(),
+        expected,
+        "{} expects {} inputs, got {}",
+        name,
+        expected,
+        input.len()
+    );
+}
+
+impl Module for Neuron {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        check_input("Neuron", self.weights.len(), input);
+        vec![self.eval(input)]
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        Some(self.weights.len())
+    }
+
+    fn out_features(&self, _input: usize) -> usize {
+        1
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
         let mut params = Vec::new();
-        for w in self.weights.iter() {
-            params.push(w.clone());
+        for (i, w) in self.weights.iter().enumerate() {
+            params.push((format!("weights.{}", i), w.clone()));
         }
-        params.push(self.bias.clone());
+        params.push(("bias".to_string(), self.bias.clone()));
         params
     }
 }
@@ -59,15 +90,24 @@ pub struct Layer {
 }
 
 impl Layer {
-    pub fn new(input: u32, output: u32, activation: bool) -> Self {
-        let mut neurons = Vec::with_capacity(output as usize);
-        for _ in 0..output {
-            neurons.push(Neuron::new(input, activation));
-        }
+    pub fn new(input: u32, output: u32, activation: Activation) -> Self {
+        Layer::with_init(input, output, activation, &Init::default())
+    }
+
+    pub fn with_init(input: u32, output: u32, activation: Activation, init: &Init) -> Self {
+        let fan = Fan {
+            fan_in: input as usize,
+            fan_out: output as usize,
+        };
+        let neurons = init
+            .sample(fan)
+            .into_iter()
+            .map(|weights| Neuron::from_weights(weights, 0.0, activation))
+            .collect();
         Layer { neurons }
     }
 
-    pub fn eval(&self, input: &Vec<Unit>) -> Vec<Unit> {
+    pub fn eval(&self, input: &[Unit]) -> Vec<Unit> {
         let mut output = Vec::with_capacity(self.neurons.len());
         for n in self.neurons.iter() {
             output.push(n.eval(input));
@@ -76,29 +116,77 @@ impl Layer {
     }
 }
 
-impl Zeroable for Layer {
-    fn parameters(&self) -> Vec<Unit> {
-        let mut params = Vec::new();
-        for n in self.neurons.iter() {
-            params.append(&mut n.parameters());
+impl Module for Layer {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        if let Some(n) = self.in_features() {
+            check_input("Layer", n, input);
         }
-        params
+        self.eval(input)
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        self.neurons.first().map(|n| n.weights.len())
+    }
+
+    fn out_features(&self, _input: usize) -> usize {
+        self.neurons.len()
+    }
+
+    fn children(&self) -> Vec<(String, &dyn Module)> {
+        self.neurons
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (format!("neurons.{}", i), n as &dyn Module))
+            .collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+        self.neurons
+            .iter_mut()
+            .map(|n| n as &mut dyn Module)
+            .collect()
     }
 }
 
+#[derive(Debug)]
 pub struct MLP {
     pub layers: Vec<Layer>,
 }
 
 impl MLP {
+    /// ReLU on every hidden layer and no activation on the output.
     pub fn new(input: u32, hidden: Vec<u32>, output: u32) -> Self {
-        let mut layers = Vec::with_capacity(hidden.len() + 1);
+        let mut activations = vec![Activation::ReLU; hidden.len()];
+        activations.push(Activation::Identity);
+        let mut sizes = hidden;
+        sizes.push(output);
+        MLP::with_activations(input, sizes, activations)
+    }
+
+    /// One layer per entry of `sizes`, each with its own activation, e.g. a
+    /// `Tanh` output for targets in [-1, 1].
+    pub fn with_activations(input: u32, sizes: Vec<u32>, activations: Vec<Activation>) -> Self {
+        MLP::with_init(input, sizes, activations, &Init::default())
+    }
+
+    /// Like [`MLP::with_activations`], drawing every layer's weights from `init`.
+    pub fn with_init(
+        input: u32,
+        sizes: Vec<u32>,
+        activations: Vec<Activation>,
+        init: &Init,
+    ) -> Self {
+        assert_eq!(
+            sizes.len(),
+            activations.len(),
+            "MLP needs one activation per layer"
+        );
+        let mut layers = Vec::with_capacity(sizes.len());
         let mut input = input;
-        for &h in hidden.iter() {
-            layers.push(Layer::new(input, h, true));
-            input = h;
+        for (&size, &activation) in sizes.iter().zip(activations.iter()) {
+            layers.push(Layer::with_init(input, size, activation, init));
+            input = size;
         }
-        layers.push(Layer::new(input, output, false));
         MLP { layers }
     }
 
@@ -111,13 +199,35 @@ impl MLP {
     }
 }
 
-impl Zeroable for MLP {
-    fn parameters(&self) -> Vec<Unit> {
-        let mut params = Vec::new();
-        for l in self.layers.iter() {
-            params.append(&mut l.parameters());
+impl Module for MLP {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        if let Some(n) = self.in_features() {
+            check_input("MLP", n, input);
         }
-        params
+        self.eval(input.to_vec())
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        self.layers.first().and_then(|l| l.in_features())
+    }
+
+    fn out_features(&self, input: usize) -> usize {
+        self.layers.iter().fold(input, |n, l| l.out_features(n))
+    }
+
+    fn children(&self) -> Vec<(String, &dyn Module)> {
+        self.layers
+            .iter()
+            .enumerate()
+            .map(|(i, l)| (format!("layers.{}", i), l as &dyn Module))
+            .collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+        self.layers
+            .iter_mut()
+            .map(|l| l as &mut dyn Module)
+            .collect()
     }
 }
 
@@ -131,9 +241,17 @@ impl fmt::Display for MLP {
 mod tests {
     use super::*;
 
+    #[test]
+    #[should_panic(expected = "MLP expects 3 inputs, got 4")]
+    fn test_forward_rejects_wrong_input_size() {
+        let mlp = MLP::new(3, vec![2], 1);
+        let input = (0..4).map(|i| Unit::from(i as f32)).collect::<Vec<_>>();
+        mlp.forward(&input);
+    }
+
     #[test]
     fn test_neuron() {
-        let n = Neuron::new(2, false);
+        let n = Neuron::new(2, Activation::Identity);
         let input = vec![Unit::from(1.0), Unit::from(2.0)];
         let output = n.eval(&input);
         println!("neuron  is {:?}", n);
@@ -142,13 +260,107 @@ mod tests {
 
     #[test]
     fn test_layer() {
-        let l = Layer::new(4, 1, false);
+        let l = Layer::new(4, 1, Activation::Identity);
         let input = vec![new_unit(1.0), new_unit(2.0), new_unit(3.0), new_unit(4.0)];
         let output = l.eval(&input);
         println!("layer is {:?}", l);
         println!("{:?}", output);
     }
 
+    #[test]
+    fn test_mlp_with_activations() {
+        let mlp = MLP::with_activations(
+            2,
+            vec![3, 1],
+            vec![Activation::LeakyReLU(0.01), Activation::Sigmoid],
+        );
+        assert_eq!(
+            mlp.layers[0].neurons[0].activation,
+            Activation::LeakyReLU(0.01)
+        );
+        let output = mlp.eval(vec![new_unit(1.0), new_unit(-2.0)]);
+        assert!(output[0].data() > 0.0 && output[0].data() < 1.0);
+    }
+
+    #[test]
+    fn test_layer_with_init() {
+        let l = Layer::with_init(3, 2, Activation::ReLU, &Init::Constant(0.5));
+        assert_eq!(l.neurons.len(), 2);
+        for n in l.neurons.iter() {
+            assert!(n.weights.iter().all(|w| w.data() == 0.5));
+            assert_eq!(n.bias.data(), 0.0);
+        }
+
+        let mlp = MLP::with_init(
+            4,
+            vec![8, 1],
+            vec![Activation::ReLU, Activation::Identity],
+            &Init::KaimingUniform,
+        );
+        let bound = (6.0f32 / 8.0).sqrt();
+        assert!(mlp.layers[1].neurons[0]
+            .weights
+            .iter()
+            .all(|w| w.data().abs() <= bound));
+    }
+
+    fn sgd_step<M: Module>(model: &mut M, xs: &[Vec<Unit>], ys: &[f32]) -> f32 {
+        let mut loss = Unit::from(0.0);
+        for (x, &y) in xs.iter().zip(ys.iter()) {
+            let d = &model.forward(x)[0] - &Unit::from(y);
+            loss = &loss + &pow(&d, 2.0);
+        }
+        model.zero_grad();
+        backward(&loss);
+        for p in model.parameters() {
+            let mut p = p.borrow_mut();
+            p.data -= 0.05 * p.grad;
+        }
+        loss.data()
+    }
+
+    #[test]
+    fn test_generic_training_over_module() {
+        let xs = vec![
+            vec![new_unit(1.0), new_unit(0.0)],
+            vec![new_unit(0.0), new_unit(1.0)],
+        ];
+        let ys = [1.0, -1.0];
+
+        let mut mlp = MLP::new(2, vec![4], 1);
+        let mut layer = Layer::new(2, 1, Activation::Identity);
+        assert_eq!(mlp.num_parameters(), 4 * 3 + 5);
+        assert_eq!(layer.num_parameters(), 3);
+
+        let (first_mlp, first_layer) =
+            (sgd_step(&mut mlp, &xs, &ys), sgd_step(&mut layer, &xs, &ys));
+        let (mut last_mlp, mut last_layer) = (first_mlp, first_layer);
+        for _ in 0..30 {
+            last_mlp = sgd_step(&mut mlp, &xs, &ys);
+            last_layer = sgd_step(&mut layer, &xs, &ys);
+        }
+        assert!(last_mlp < first_mlp);
+        assert!(last_layer < first_layer);
+
+        Module::eval(&mut mlp);
+        mlp.train();
+    }
+
+    #[test]
+    fn test_named_parameters() {
+        let mlp = MLP::new(2, vec![3], 1);
+        let names = mlp
+            .named_parameters()
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), mlp.num_parameters());
+        assert_eq!(names[0], "layers.0.neurons.0.weights.0");
+        assert_eq!(names[2], "layers.0.neurons.0.bias");
+        assert_eq!(names.last().unwrap(), "layers.1.neurons.0.bias");
+        assert_eq!(mlp.children().len(), 2);
+    }
+
     //接下来需要测试一下MLP在只有部分节点时候，权重更新是否正常
     #[test]
     fn test_mlp() {
@@ -172,7 +384,7 @@ mod tests {
         }
 
         let x = vec![new_unit(1.0), new_unit(2.0)];
-        let _y = vec![new_unit(2.0), new_unit(4.0)];
+        let _y = [new_unit(2.0), new_unit(4.0)];
 
         let _ypred = mlp.eval(x);
         //println!("ypred is {}",ypred);
@@ -188,7 +400,7 @@ mod tests {
             vec![new_unit(1.0), new_unit(1.0), new_unit(-1.0)],
         ];
 
-        let ys = vec![new_unit(1.0), new_unit(-1.0), new_unit(-1.0), new_unit(1.0)];
+        let ys = [new_unit(1.0), new_unit(-1.0), new_unit(-1.0), new_unit(1.0)];
 
         for k in 0..20 {
             let mut ypred = Vec::new();
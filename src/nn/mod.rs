@@ -1,15 +1,163 @@
 use crate::fundamental::Unit;
+use crate::nn::state::{IncompatibleKeys, StateDict};
 
-mod linear;
+pub mod activation;
+pub mod attention;
+pub mod combinator;
+pub mod conv;
+pub mod dropout;
+pub mod embedding;
+pub mod init;
+pub mod linear;
+pub mod loss;
 pub mod mlp;
+pub mod norm;
+pub mod pool;
+pub mod recurrent;
+pub mod sequential;
+pub mod state;
+
+/// A trainable building block. Containers list their sub-modules in
+/// `children`, and parameter names, counts and mode switches are derived
+/// from that tree, so generic training code only needs `Module`.
+pub trait Module {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit>;
+
+    /// Runs a batch of samples. Modules whose output depends on the whole
+    /// batch, such as batch norm, override this; the rest map `forward`.
+    fn forward_batch(&self, batch: &[Vec<Unit>]) -> Vec<Vec<Unit>> {
+        batch.iter().map(|x| self.forward(x)).collect()
+    }
+
+    /// Number of inputs the module requires, if it is fixed.
+    fn in_features(&self) -> Option<usize> {
+        None
+    }
+
+    /// Number of outputs for `input` inputs. Size-preserving modules such as
+    /// activations keep the default.
+    fn out_features(&self, input: usize) -> usize {
+        input
+    }
+
+    /// Sub-modules together with the name of the field holding them.
+    fn children(&self) -> Vec<(String, &dyn Module)> {
+        Vec::new()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+        Vec::new()
+    }
+
+    /// Every parameter with a dotted path such as `layers.1.neurons.3.bias`.
+    /// Modules that own parameters directly override this.
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
+        let mut params = Vec::new();
+        for (name, child) in self.children() {
+            for (p_name, p) in child.named_parameters() {
+                params.push((format!("{}.{}", name, p_name), p));
+            }
+        }
+        params
+    }
+
+    fn parameters(&self) -> Vec<Unit> {
+        self.named_parameters()
+            .into_iter()
+            .map(|(_, p)| p)
+            .collect()
+    }
+
+    fn num_parameters(&self) -> usize {
+        self.parameters().len()
+    }
+
+    /// State that is saved with the parameters but not trained, such as the
+    /// running statistics of batch norm. Modules that own buffers override
+    /// this and `set_buffer`.
+    fn named_buffers(&self) -> Vec<(String, f32)> {
+        let mut buffers = Vec::new();
+        for (name, child) in self.children() {
+            for (b_name, b) in child.named_buffers() {
+                buffers.push((format!("{}.{}", name, b_name), b));
+            }
+        }
+        buffers
+    }
+
+    /// Sets the buffer called `name` in `named_buffers`, returning whether
+    /// there is one.
+    fn set_buffer(&self, name: &str, value: f32) -> bool {
+        self.children().into_iter().any(|(child_name, child)| {
+            name.strip_prefix(child_name.as_str())
+                .and_then(|rest| rest.strip_prefix('.'))
+                .is_some_and(|rest| child.set_buffer(rest, value))
+        })
+    }
+
+    /// The value of every parameter and buffer under its dotted name.
+    fn state_dict(&self) -> StateDict {
+        state::collect(self)
+    }
+
+    /// Copies values from `state` into the parameters and buffers of the
+    /// same name. With `strict`, missing or unexpected keys are an error and
+    /// nothing is loaded; otherwise matching keys are loaded and the others
+    /// reported.
+    fn load_state_dict(
+        &mut self,
+        state: &StateDict,
+        strict: bool,
+    ) -> Result<IncompatibleKeys, IncompatibleKeys> {
+        state::load(self, state, strict)
+    }
+
+    /// Parameters that are not frozen, the ones an optimizer should update.
+    fn trainable_parameters(&self) -> Vec<Unit> {
+        self.parameters()
+            .into_iter()
+            .filter(|p| p.requires_grad())
+            .collect()
+    }
+
+    /// Stops gradients for every parameter, e.g. `mlp.layers[0].freeze()` to
+    /// keep the lowest layer of a pretrained network fixed while fine-tuning.
+    fn freeze(&self) {
+        for p in self.parameters() {
+            p.set_requires_grad(false);
+        }
+    }
+
+    fn unfreeze(&self) {
+        for p in self.parameters() {
+            p.set_requires_grad(true);
+        }
+    }
 
-pub trait Zeroable {
     fn zero_grad(&mut self) {
         for p in self.parameters() {
             p.zero_grad();
-            //p.borrow_mut().grad = 0.0;
         }
     }
 
-    fn parameters(&self) -> Vec<Unit>;
+    /// Switches between training and inference behaviour. Containers pass it
+    /// down to their children; modules without such behaviour ignore it.
+    fn set_training(&mut self, training: bool) {
+        for child in self.children_mut() {
+            child.set_training(training);
+        }
+    }
+
+    fn train(&mut self) {
+        self.set_training(true);
+    }
+
+    /// Inference mode. On types that also have an inherent `eval(input)`,
+    /// call it as `Module::eval(&mut model)`.
+    fn eval(&mut self) {
+        self.set_training(false);
+    }
 }
+
+/// The parameter-only trait `Module` grew out of, kept as an alias.
+pub use Module as Zeroable;
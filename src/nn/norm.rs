@@ -0,0 +1,364 @@
+use std::cell::RefCell;
+
+use super::*;
+use crate::fundamental::op::{add, mul, pow, sub};
+use crate::fundamental::unit::new_unit;
+
+fn mean(xs: &[Unit]) -> Unit {
+    let total = xs[1..].iter().fold(xs[0].clone(), |acc, x| add(&acc, x));
+    mul(&total, &Unit::from(1.0 / xs.len() as f32))
+}
+
+/// `(x - mean) / sqrt(var + eps)` built from unit ops, so gradients flow
+/// through the mean and the (biased) variance as well as through `x`.
+pub fn normalize(xs: &[Unit], eps: f32) -> Vec<Unit> {
+    assert!(!xs.is_empty(), "cannot normalize an empty input");
+    let m = mean(xs);
+    let centered = xs.iter().map(|x| sub(x, &m)).collect::<Vec<_>>();
+    let squares = centered.iter().map(|c| mul(c, c)).collect::<Vec<_>>();
+    let inv_std = pow(&add(&mean(&squares), &Unit::from(eps)), -0.5);
+    centered.iter().map(|c| mul(c, &inv_std)).collect()
+}
+
+fn affine(xs: Vec<Unit>, weight: &[Unit], bias: &[Unit]) -> Vec<Unit> {
+    xs.iter()
+        .zip(weight.iter().zip(bias.iter()))
+        .map(|(x, (w, b))| add(&mul(x, w), b))
+        .collect()
+}
+
+fn affine_parameters(weight: &[Unit], bias: &[Unit]) -> Vec<(String, Unit)> {
+    let weights = weight
+        .iter()
+        .enumerate()
+        .map(|(i, w)| (format!("weight.{}", i), w.clone()));
+    let biases = bias
+        .iter()
+        .enumerate()
+        .map(|(i, b)| (format!("bias.{}", i), b.clone()));
+    weights.chain(biases).collect()
+}
+
+/// Normalizes each sample over its features, then applies a learnable
+/// per-feature gain (initialized to 1) and bias (initialized to 0).
+#[derive(Debug)]
+pub struct LayerNorm {
+    pub dim: usize,
+    pub eps: f32,
+    pub weight: Vec<Unit>,
+    pub bias: Vec<Unit>,
+}
+
+impl LayerNorm {
+    pub fn new(dim: usize) -> Self {
+        LayerNorm {
+            dim,
+            eps: 1e-5,
+            weight: (0..dim).map(|_| new_unit(1.0)).collect(),
+            bias: (0..dim).map(|_| new_unit(0.0)).collect(),
+        }
+    }
+}
+
+impl Module for LayerNorm {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        assert_eq!(
+            input.len(),
+            self.dim,
+            "LayerNorm expects {} inputs, got {}",
+            self.dim,
+            input.len()
+        );
+        affine(normalize(input, self.eps), &self.weight, &self.bias)
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        Some(self.dim)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
+        affine_parameters(&self.weight, &self.bias)
+    }
+}
+
+/// Normalizes each feature over the batch while training and keeps running
+/// estimates of its mean and variance for eval mode, where samples are
+/// normalized one at a time with those estimates.
+///
+/// Training needs the whole batch, so call `forward_batch`; a single-sample
+/// `forward` is only valid in eval mode.
+#[derive(Debug)]
+pub struct BatchNorm1d {
+    pub num_features: usize,
+    pub eps: f32,
+    /// weight of the newest batch in the running statistics
+    pub momentum: f32,
+    pub weight: Vec<Unit>,
+    pub bias: Vec<Unit>,
+    pub running_mean: RefCell<Vec<f32>>,
+    pub running_var: RefCell<Vec<f32>>,
+    training: bool,
+}
+
+impl BatchNorm1d {
+    pub fn new(num_features: usize) -> Self {
+        BatchNorm1d {
+            num_features,
+            eps: 1e-5,
+            momentum: 0.1,
+            weight: (0..num_features).map(|_| new_unit(1.0)).collect(),
+            bias: (0..num_features).map(|_| new_unit(0.0)).collect(),
+            running_mean: RefCell::new(vec![0.0; num_features]),
+            running_var: RefCell::new(vec![1.0; num_features]),
+            training: true,
+        }
+    }
+
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    fn check(&self, sample: &[Unit]) {
+        assert_eq!(
+            sample.len(),
+            self.num_features,
+            "BatchNorm1d expects {} features, got {}",
+            self.num_features,
+            sample.len()
+        );
+    }
+
+    /// Folds the batch statistics of one feature into the running estimates,
+    /// using the unbiased variance like the usual formulation.
+    fn update_running(&self, feature: usize, column: &[Unit]) {
+        let n = column.len() as f32;
+        let m = column.iter().map(|x| x.data()).sum::<f32>() / n;
+        let v = column.iter().map(|x| (x.data() - m).powi(2)).sum::<f32>() / (n - 1.0);
+        let k = self.momentum;
+        let mut rm = self.running_mean.borrow_mut();
+        let mut rv = self.running_var.borrow_mut();
+        rm[feature] = (1.0 - k) * rm[feature] + k * m;
+        rv[feature] = (1.0 - k) * rv[feature] + k * v;
+    }
+}
+
+impl Module for BatchNorm1d {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        assert!(
+            !self.training,
+            "BatchNorm1d needs a whole batch in training mode; use forward_batch or switch to eval"
+        );
+        self.check(input);
+        let rm = self.running_mean.borrow();
+        let rv = self.running_var.borrow();
+        let normalized = input
+            .iter()
+            .enumerate()
+            .map(|(j, x)| {
+                let scale = 1.0 / (rv[j] + self.eps).sqrt();
+                mul(&sub(x, &Unit::from(rm[j])), &Unit::from(scale))
+            })
+            .collect();
+        affine(normalized, &self.weight, &self.bias)
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Unit>]) -> Vec<Vec<Unit>> {
+        if !self.training {
+            return batch.iter().map(|x| self.forward(x)).collect();
+        }
+        assert!(
+            batch.len() > 1,
+            "BatchNorm1d needs more than one sample per batch in training mode"
+        );
+        batch.iter().for_each(|x| self.check(x));
+        let mut output = vec![Vec::with_capacity(self.num_features); batch.len()];
+        for j in 0..self.num_features {
+            let column = batch.iter().map(|x| x[j].clone()).collect::<Vec<_>>();
+            self.update_running(j, &column);
+            for (out, y) in output.iter_mut().zip(normalize(&column, self.eps)) {
+                out.push(add(&mul(&y, &self.weight[j]), &self.bias[j]));
+            }
+        }
+        output
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        Some(self.num_features)
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Unit)> {
+        affine_parameters(&self.weight, &self.bias)
+    }
+
+    /// `running_mean.i` and `running_var.i`, so eval mode survives a save and load.
+    fn named_buffers(&self) -> Vec<(String, f32)> {
+        let mut buffers = Vec::new();
+        for (name, values) in [
+            ("running_mean", &self.running_mean),
+            ("running_var", &self.running_var),
+        ] {
+            for (i, v) in values.borrow().iter().enumerate() {
+                buffers.push((format!("{}.{}", name, i), *v));
+            }
+        }
+        buffers
+    }
+
+    fn set_buffer(&self, name: &str, value: f32) -> bool {
+        let values = match name.split_once('.') {
+            Some(("running_mean", i)) => (&self.running_mean, i),
+            Some(("running_var", i)) => (&self.running_var, i),
+            _ => return false,
+        };
+        match values.1.parse::<usize>() {
+            Ok(i) if i < self.num_features => {
+                values.0.borrow_mut()[i] = value;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::backward;
+    use crate::fundamental::tensor::{self, Tensor};
+    use crate::nn::sequential::Sequential;
+
+    fn units(values: &[f32]) -> Vec<Unit> {
+        values.iter().map(|&v| new_unit(v)).collect()
+    }
+
+    /// Sum of `output * weights`, a loss that weighs every output differently.
+    fn weighted(outputs: &[Unit]) -> Unit {
+        let terms = outputs
+            .iter()
+            .enumerate()
+            .map(|(i, y)| mul(y, &Unit::from((i as f32 * 0.7).sin())))
+            .collect::<Vec<_>>();
+        terms[1..]
+            .iter()
+            .fold(terms[0].clone(), |acc, t| add(&acc, t))
+    }
+
+    #[test]
+    fn test_layer_norm_matches_tensor_gradients() {
+        let values = [0.5, -1.0, 2.0, 3.0];
+        let ln = LayerNorm::new(4);
+        ln.weight[1].borrow_mut().data = 2.0;
+        ln.bias[2].borrow_mut().data = -1.0;
+        let x = units(&values);
+        let y = ln.forward(&x);
+        backward(&weighted(&y));
+
+        let xt = Tensor::new(values.to_vec(), &[4]);
+        let w = Tensor::new(ln.weight.iter().map(|u| u.data()).collect(), &[4]);
+        let b = Tensor::new(ln.bias.iter().map(|u| u.data()).collect(), &[4]);
+        let yt = tensor::layer_norm(&xt, Some(&w), Some(&b), ln.eps);
+        let coeffs = Tensor::new((0..4).map(|i| (i as f32 * 0.7).sin()).collect(), &[4]);
+        tensor::backward(&tensor::sum(&tensor::mul(&yt, &coeffs), &[], false));
+
+        for i in 0..4 {
+            assert!((y[i].data() - yt.data()[i]).abs() < 1e-5);
+            assert!((x[i].grad() - xt.grad()[i]).abs() < 1e-4);
+            assert!((ln.weight[i].grad() - w.grad()[i]).abs() < 1e-4);
+        }
+        assert_eq!(ln.num_parameters(), 8);
+        assert_eq!(ln.named_parameters()[5].0, "bias.1");
+    }
+
+    #[test]
+    fn test_batch_norm_training_and_running_stats() {
+        let mut bn = BatchNorm1d::new(2);
+        bn.momentum = 0.5;
+        let batch = vec![
+            units(&[1.0, 10.0]),
+            units(&[3.0, 20.0]),
+            units(&[5.0, 30.0]),
+        ];
+        let out = bn.forward_batch(&batch);
+        assert!((out[0][0].data() + 1.2247).abs() < 1e-3);
+        assert!(out[1][1].data().abs() < 1e-5);
+        assert_eq!(*bn.running_mean.borrow(), vec![1.5, 10.0]);
+        assert_eq!(*bn.running_var.borrow(), vec![2.5, 50.5]);
+
+        // the normalized column sums to zero whatever the inputs, so a plain
+        // sum has no gradient; a weighted one flows through the statistics
+        let xt = Tensor::new(vec![1.0, 10.0, 3.0, 20.0, 5.0, 30.0], &[3, 2]);
+        let mut stats = tensor::RunningStats::new(2, 0.5);
+        let yt = tensor::batch_norm(&xt, &mut stats, true, None, None, bn.eps);
+        let flat = out.concat();
+        backward(&weighted(&flat));
+        let coeffs = Tensor::new((0..6).map(|i| (i as f32 * 0.7).sin()).collect(), &[3, 2]);
+        tensor::backward(&tensor::sum(&tensor::mul(&yt, &coeffs), &[], false));
+        let grads = batch.concat().iter().map(|x| x.grad()).collect::<Vec<_>>();
+        for (a, b) in grads.iter().zip(xt.grad()) {
+            assert!((a - b).abs() < 1e-4, "{:?} vs {:?}", grads, xt.grad());
+        }
+
+        Module::eval(&mut bn);
+        let y = bn.forward(&units(&[1.5, 10.0]));
+        assert_eq!(y[0].data(), 0.0);
+        assert_eq!(y[1].data(), 0.0);
+        assert_eq!(*bn.running_mean.borrow(), vec![1.5, 10.0]);
+    }
+
+    #[test]
+    fn test_running_stats_are_saved_with_the_model() {
+        use crate::io::safetensors::{load_module_from_bytes, module_to_bytes, Dtype};
+
+        let build = || Sequential::new().linear(3, 2).then(BatchNorm1d::new(2));
+        let mut model = build();
+        model.forward_batch(&[units(&[1.0, 2.0, 3.0]), units(&[-1.0, 0.5, 2.0])]);
+        Module::eval(&mut model);
+        let state = model.state_dict();
+        assert_eq!(state.len(), model.num_parameters() + 4);
+        let buffers = model.named_buffers();
+        assert_eq!(buffers[3].0, "1.running_var.1");
+        assert_ne!(state["1.running_var.1"], 1.0);
+
+        let x = units(&[0.3, -0.2, 1.0]);
+        let expected = model.forward(&x)[1].data();
+
+        let mut from_state = build();
+        assert!(from_state.load_state_dict(&state, true).unwrap().is_empty());
+        let mut from_file = build();
+        let bytes = module_to_bytes(&model, Dtype::F32).unwrap();
+        load_module_from_bytes(&mut from_file, &bytes, true).unwrap();
+        for other in [&mut from_state, &mut from_file] {
+            Module::eval(other);
+            assert_eq!(other.forward(&x)[1].data(), expected);
+        }
+    }
+
+    #[test]
+    fn test_batch_norm_in_sequential() {
+        let mut model = Sequential::new()
+            .linear(3, 4)
+            .then(BatchNorm1d::new(4))
+            .relu();
+        let batch = vec![units(&[1.0, 0.0, -1.0]), units(&[0.5, 2.0, 1.0])];
+        let out = model.forward_batch(&batch);
+        assert_eq!(out.len(), 2);
+        assert!(out.iter().all(|y| y.len() == 4));
+        assert!(model
+            .named_parameters()
+            .iter()
+            .any(|(name, _)| name == "1.weight.3"));
+
+        model.eval();
+        assert_eq!(model.forward(&batch[0]).len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "needs a whole batch in training mode")]
+    fn test_batch_norm_single_sample_in_training() {
+        BatchNorm1d::new(2).forward(&units(&[1.0, 2.0]));
+    }
+}
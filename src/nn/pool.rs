@@ -0,0 +1,335 @@
+use super::*;
+use crate::fundamental::op::dot;
+use crate::nn::conv::Window;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pooling {
+    /// The largest value under the window, which alone receives the gradient.
+    Max,
+    /// The mean over the whole window, padding counted as zeros.
+    Avg,
+}
+
+fn pool(
+    name: &str,
+    pooling: Pooling,
+    window: &Window,
+    channels: usize,
+    input: &[Unit],
+    size: (usize, usize),
+) -> Vec<Unit> {
+    window.check(name);
+    assert!(
+        window.padding.0 <= window.kernel.0 / 2 && window.padding.1 <= window.kernel.1 / 2,
+        "{}: padding {:?} must be at most half the kernel size {:?}",
+        name,
+        window.padding,
+        window.kernel
+    );
+    let plane = size.0 * size.1;
+    assert_eq!(
+        input.len(),
+        channels * plane,
+        "{} expects {} channels of {}x{} = {} inputs, got {}",
+        name,
+        channels,
+        size.0,
+        size.1,
+        channels * plane,
+        input.len()
+    );
+    let (oh, ow) = window.output_size(name, size);
+    let scale = Unit::from(1.0 / (window.kernel.0 * window.kernel.1) as f32);
+    let mut output = Vec::with_capacity(channels * oh * ow);
+    for c in 0..channels {
+        let channel = &input[c * plane..(c + 1) * plane];
+        for oy in 0..oh {
+            for ox in 0..ow {
+                let under = window
+                    .taps(size, oy, ox)
+                    .into_iter()
+                    .flatten()
+                    .map(|i| &channel[i]);
+                output.push(match pooling {
+                    Pooling::Max => under
+                        .reduce(|best, u| if u.data() > best.data() { u } else { best })
+                        .unwrap()
+                        .clone(),
+                    Pooling::Avg => {
+                        let values = under.cloned().collect::<Vec<_>>();
+                        dot(&values, &vec![scale.clone(); values.len()])
+                    }
+                });
+            }
+        }
+    }
+    output
+}
+
+/// Max or average pooling over `channels` signals stored one after another.
+/// The stride defaults to the kernel size.
+#[derive(Debug)]
+pub struct Pool1d {
+    pub pooling: Pooling,
+    pub channels: usize,
+    window: Window,
+}
+
+impl Pool1d {
+    pub fn new(pooling: Pooling, channels: usize, kernel_size: usize) -> Self {
+        let mut window = Window::new((1, kernel_size));
+        window.stride = (1, kernel_size);
+        Pool1d {
+            pooling,
+            channels,
+            window,
+        }
+    }
+
+    pub fn max(channels: usize, kernel_size: usize) -> Self {
+        Pool1d::new(Pooling::Max, channels, kernel_size)
+    }
+
+    pub fn avg(channels: usize, kernel_size: usize) -> Self {
+        Pool1d::new(Pooling::Avg, channels, kernel_size)
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        self.window.stride.1 = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.window.padding.1 = padding;
+        self
+    }
+
+    pub fn output_len(&self, length: usize) -> usize {
+        self.window.output_size("Pool1d", (1, length)).1
+    }
+
+    fn length(&self, input: usize) -> usize {
+        assert!(
+            input.is_multiple_of(self.channels),
+            "Pool1d expects {} channels of equal length, got {} inputs",
+            self.channels,
+            input
+        );
+        input / self.channels
+    }
+}
+
+impl Module for Pool1d {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        let length = self.length(input.len());
+        pool(
+            "Pool1d",
+            self.pooling,
+            &self.window,
+            self.channels,
+            input,
+            (1, length),
+        )
+    }
+
+    fn out_features(&self, input: usize) -> usize {
+        self.channels * self.output_len(self.length(input))
+    }
+}
+
+/// Max or average pooling over `channels` row-major images. As a `Module` it
+/// needs the image size, set with [`Pool2d::input_size`].
+#[derive(Debug)]
+pub struct Pool2d {
+    pub pooling: Pooling,
+    pub channels: usize,
+    window: Window,
+    input_size: Option<(usize, usize)>,
+}
+
+impl Pool2d {
+    pub fn new(pooling: Pooling, channels: usize, kernel_size: (usize, usize)) -> Self {
+        let mut window = Window::new(kernel_size);
+        window.stride = kernel_size;
+        Pool2d {
+            pooling,
+            channels,
+            window,
+            input_size: None,
+        }
+    }
+
+    pub fn max(channels: usize, kernel_size: (usize, usize)) -> Self {
+        Pool2d::new(Pooling::Max, channels, kernel_size)
+    }
+
+    pub fn avg(channels: usize, kernel_size: (usize, usize)) -> Self {
+        Pool2d::new(Pooling::Avg, channels, kernel_size)
+    }
+
+    pub fn stride(mut self, stride: (usize, usize)) -> Self {
+        self.window.stride = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: (usize, usize)) -> Self {
+        self.window.padding = padding;
+        self
+    }
+
+    pub fn input_size(mut self, height: usize, width: usize) -> Self {
+        self.input_size = Some((height, width));
+        self
+    }
+
+    pub fn output_size(&self, height: usize, width: usize) -> (usize, usize) {
+        self.window.output_size("Pool2d", (height, width))
+    }
+
+    pub fn eval(&self, input: &[Unit], height: usize, width: usize) -> Vec<Unit> {
+        pool(
+            "Pool2d",
+            self.pooling,
+            &self.window,
+            self.channels,
+            input,
+            (height, width),
+        )
+    }
+
+    fn size(&self) -> (usize, usize) {
+        self.input_size
+            .expect("Pool2d needs its input size; set it with Pool2d::input_size(height, width)")
+    }
+}
+
+impl Module for Pool2d {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        let (h, w) = self.size();
+        self.eval(input, h, w)
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        self.input_size.map(|(h, w)| self.channels * h * w)
+    }
+
+    fn out_features(&self, _input: usize) -> usize {
+        let (h, w) = self.size();
+        let (oh, ow) = self.output_size(h, w);
+        self.channels * oh * ow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::{add, backward};
+    use crate::fundamental::unit::new_unit;
+    use crate::nn::conv::Conv2d;
+    use crate::nn::init::{reseed, Init};
+    use crate::nn::loss::{cross_entropy, Reduction};
+    use crate::nn::sequential::Sequential;
+
+    fn units(values: &[f32]) -> Vec<Unit> {
+        values.iter().map(|&v| new_unit(v)).collect()
+    }
+
+    fn data(xs: &[Unit]) -> Vec<f32> {
+        xs.iter().map(|u| u.data()).collect()
+    }
+
+    fn sum(xs: &[Unit]) -> Unit {
+        xs[1..].iter().fold(xs[0].clone(), |acc, x| add(&acc, x))
+    }
+
+    #[test]
+    fn test_pool1d_max_and_avg() {
+        // two channels of length 4
+        let x = units(&[1.0, 3.0, 2.0, 0.0, -1.0, -2.0, 4.0, 8.0]);
+        let max = Pool1d::max(2, 2);
+        let y = max.forward(&x);
+        assert_eq!(data(&y), vec![3.0, 2.0, -1.0, 8.0]);
+        backward(&sum(&y));
+        let grads = x.iter().map(|u| u.grad()).collect::<Vec<_>>();
+        assert_eq!(grads, vec![0.0, 1.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+
+        let x = units(&[1.0, 3.0, 2.0, 0.0]);
+        let avg = Pool1d::avg(1, 2).stride(1).padding(1);
+        assert_eq!(avg.out_features(4), 5);
+        let y = avg.forward(&x);
+        assert_eq!(data(&y), vec![0.5, 2.0, 2.5, 1.0, 0.0]);
+        backward(&sum(&y));
+        assert!(x.iter().all(|u| u.grad() == 1.0));
+    }
+
+    #[test]
+    fn test_pool2d_overlapping_windows() {
+        let x = units(&[1.0, 5.0, 2.0, 4.0, 0.0, 3.0, 7.0, 6.0, 9.0]);
+        let pool = Pool2d::max(1, (2, 2)).stride((1, 1)).input_size(3, 3);
+        let y = pool.forward(&x);
+        assert_eq!(data(&y), vec![5.0, 5.0, 7.0, 9.0]);
+        backward(&sum(&y));
+        // 5 wins two windows
+        assert_eq!(x[1].grad(), 2.0);
+        assert_eq!(x[0].grad(), 0.0);
+
+        let avg = Pool2d::avg(1, (3, 3)).input_size(3, 3);
+        assert!((avg.forward(&x)[0].data() - 37.0 / 9.0).abs() < 1e-5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool2d: padding (2, 0) must be at most half the kernel size (2, 2)")]
+    fn test_pool_padding_too_large() {
+        Pool2d::max(1, (2, 2))
+            .padding((2, 0))
+            .eval(&units(&[0.0; 4]), 2, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Pool1d expects 3 channels of equal length, got 4 inputs")]
+    fn test_pool1d_uneven_channels() {
+        Pool1d::avg(3, 2).forward(&units(&[0.0; 4]));
+    }
+
+    /// Tells vertical from horizontal bars on 4x4 images.
+    #[test]
+    fn test_small_cnn_learns_bars() {
+        let model = Sequential::new()
+            .then(Conv2d::with_init(1, 4, (2, 2), true, &Init::KaimingUniform).input_size(4, 4))
+            .relu()
+            .then(Pool2d::max(4, (3, 3)).input_size(3, 3))
+            .linear(4, 2);
+        reseed(&model, 1.0, 3);
+        let image = |vertical: bool, at: usize| {
+            let pixels = (0..16)
+                .map(|i| {
+                    let (y, x) = (i / 4, i % 4);
+                    let on = if vertical { x == at } else { y == at };
+                    if on {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                })
+                .collect::<Vec<f32>>();
+            units(&pixels)
+        };
+        let images = (0..4)
+            .flat_map(|at| vec![image(true, at), image(false, at)])
+            .collect::<Vec<_>>();
+        let labels = (0..8).map(|i| i % 2).collect::<Vec<_>>();
+        let loss = || {
+            let logits = images.iter().map(|x| model.forward(x)).collect::<Vec<_>>();
+            cross_entropy(&logits, &labels, Reduction::Mean).remove(0)
+        };
+
+        let first = loss().data();
+        for _ in 0..200 {
+            model.parameters().iter().for_each(|p| p.zero_grad());
+            backward(&loss());
+            model.parameters().iter().for_each(|p| p.adjust(-0.1));
+        }
+        let last = loss().data();
+        assert!(last < first / 2.0, "loss went from {} to {}", first, last);
+    }
+}
@@ -0,0 +1,408 @@
+use super::*;
+use crate::fundamental::op::{add, detach, mul, sigmoid, sub, tanh};
+use crate::nn::linear::Linear;
+
+/// One step of a recurrent network. `State` is what is carried from one step
+/// to the next: the hidden vector, plus the cell vector for an LSTM.
+pub trait RecurrentCell: Module {
+    type State: Clone;
+
+    fn hidden_size(&self) -> usize;
+
+    /// All-zero state to start a sequence from.
+    fn init_state(&self) -> Self::State;
+
+    fn step(&self, input: &[Unit], state: &Self::State) -> Self::State;
+
+    /// The part of the state that is exposed as the step's output.
+    fn output(state: &Self::State) -> Vec<Unit>;
+
+    /// The same values as fresh leaves, cutting the graph behind them.
+    fn detach_state(state: &Self::State) -> Self::State;
+}
+
+fn detach_all(xs: &[Unit]) -> Vec<Unit> {
+    xs.iter().map(detach).collect()
+}
+
+fn zeros(n: usize) -> Vec<Unit> {
+    (0..n).map(|_| Unit::from(0.0)).collect()
+}
+
+/// Runs `cell` over `inputs`, starting from `state` or from zeros, and returns
+/// the output of every step together with the final state.
+///
+/// With `truncate: Some(k)` the state is detached every `k` steps, so a
+/// backward pass through any of the outputs reaches back at most `k` steps
+/// (truncated backpropagation through time). `None` keeps the full graph.
+pub fn unroll<C: RecurrentCell>(
+    cell: &C,
+    inputs: &[Vec<Unit>],
+    state: Option<C::State>,
+    truncate: Option<usize>,
+) -> (Vec<Vec<Unit>>, C::State) {
+    assert_ne!(truncate, Some(0), "truncation length must be at least 1");
+    let mut state = state.unwrap_or_else(|| cell.init_state());
+    let mut outputs = Vec::with_capacity(inputs.len());
+    for (t, x) in inputs.iter().enumerate() {
+        if let Some(k) = truncate {
+            if t > 0 && t % k == 0 {
+                state = C::detach_state(&state);
+            }
+        }
+        state = cell.step(x, &state);
+        outputs.push(C::output(&state));
+    }
+    (outputs, state)
+}
+
+/// Splits `input` into the step input and the carried state for the
+/// single-step `Module::forward` of the cells.
+fn split_input<'a>(
+    name: &str,
+    input: &'a [Unit],
+    in_features: usize,
+    state: usize,
+) -> (&'a [Unit], &'a [Unit]) {
+    assert_eq!(
+        input.len(),
+        in_features + state,
+        "{} expects {} inputs followed by {} state values, got {} values",
+        name,
+        in_features,
+        state,
+        input.len()
+    );
+    input.split_at(in_features)
+}
+
+/// Elman cell: `h' = tanh(W_ih x + b + W_hh h)`.
+#[derive(Debug)]
+pub struct RnnCell {
+    pub ih: Linear,
+    pub hh: Linear,
+}
+
+impl RnnCell {
+    pub fn new(input_size: usize, hidden_size: usize) -> Self {
+        RnnCell {
+            ih: Linear::new(input_size, hidden_size, true),
+            hh: Linear::new(hidden_size, hidden_size, false),
+        }
+    }
+}
+
+impl RecurrentCell for RnnCell {
+    type State = Vec<Unit>;
+
+    fn hidden_size(&self) -> usize {
+        self.hh.out_features
+    }
+
+    fn init_state(&self) -> Vec<Unit> {
+        zeros(self.hidden_size())
+    }
+
+    fn step(&self, input: &[Unit], h: &Vec<Unit>) -> Vec<Unit> {
+        self.ih
+            .eval(input)
+            .iter()
+            .zip(self.hh.eval(h).iter())
+            .map(|(a, b)| tanh(&add(a, b)))
+            .collect()
+    }
+
+    fn output(h: &Vec<Unit>) -> Vec<Unit> {
+        h.clone()
+    }
+
+    fn detach_state(h: &Vec<Unit>) -> Vec<Unit> {
+        detach_all(h)
+    }
+}
+
+/// Gated recurrent unit, with the reset gate applied after the hidden
+/// projection:
+///
+/// ```text
+/// r = σ(W_ir x + W_hr h + b_r)        z = σ(W_iz x + W_hz h + b_z)
+/// n = tanh(W_in x + b_in + r ⊙ (W_hn h + b_hn))
+/// h' = (1 - z) ⊙ n + z ⊙ h
+/// ```
+#[derive(Debug)]
+pub struct GruCell {
+    /// the r, z and n projections of the input, stacked
+    pub ih: Linear,
+    pub hh: Linear,
+}
+
+impl GruCell {
+    pub fn new(input_size: usize, hidden_size: usize) -> Self {
+        GruCell {
+            ih: Linear::new(input_size, 3 * hidden_size, true),
+            hh: Linear::new(hidden_size, 3 * hidden_size, true),
+        }
+    }
+}
+
+impl RecurrentCell for GruCell {
+    type State = Vec<Unit>;
+
+    fn hidden_size(&self) -> usize {
+        self.hh.in_features
+    }
+
+    fn init_state(&self) -> Vec<Unit> {
+        zeros(self.hidden_size())
+    }
+
+    fn step(&self, input: &[Unit], h: &Vec<Unit>) -> Vec<Unit> {
+        let n = self.hidden_size();
+        let (gi, gh) = (self.ih.eval(input), self.hh.eval(h));
+        let one = Unit::from(1.0);
+        (0..n)
+            .map(|j| {
+                let r = sigmoid(&add(&gi[j], &gh[j]));
+                let z = sigmoid(&add(&gi[n + j], &gh[n + j]));
+                let candidate = tanh(&add(&gi[2 * n + j], &mul(&r, &gh[2 * n + j])));
+                add(&mul(&sub(&one, &z), &candidate), &mul(&z, &h[j]))
+            })
+            .collect()
+    }
+
+    fn output(h: &Vec<Unit>) -> Vec<Unit> {
+        h.clone()
+    }
+
+    fn detach_state(h: &Vec<Unit>) -> Vec<Unit> {
+        detach_all(h)
+    }
+}
+
+/// Hidden and cell vectors carried by an [`LstmCell`].
+#[derive(Clone, Debug)]
+pub struct LstmState {
+    pub h: Vec<Unit>,
+    pub c: Vec<Unit>,
+}
+
+/// Long short-term memory cell:
+///
+/// ```text
+/// i, f, g, o = σ, σ, tanh, σ of (W_i x + b + W_h h), split in four
+/// c' = f ⊙ c + i ⊙ g
+/// h' = o ⊙ tanh(c')
+/// ```
+#[derive(Debug)]
+pub struct LstmCell {
+    /// the i, f, g and o projections of the input, stacked
+    pub ih: Linear,
+    pub hh: Linear,
+}
+
+impl LstmCell {
+    pub fn new(input_size: usize, hidden_size: usize) -> Self {
+        LstmCell {
+            ih: Linear::new(input_size, 4 * hidden_size, true),
+            hh: Linear::new(hidden_size, 4 * hidden_size, false),
+        }
+    }
+}
+
+impl RecurrentCell for LstmCell {
+    type State = LstmState;
+
+    fn hidden_size(&self) -> usize {
+        self.hh.in_features
+    }
+
+    fn init_state(&self) -> LstmState {
+        LstmState {
+            h: zeros(self.hidden_size()),
+            c: zeros(self.hidden_size()),
+        }
+    }
+
+    fn step(&self, input: &[Unit], state: &LstmState) -> LstmState {
+        let n = self.hidden_size();
+        let gates = self
+            .ih
+            .eval(input)
+            .iter()
+            .zip(self.hh.eval(&state.h).iter())
+            .map(|(a, b)| add(a, b))
+            .collect::<Vec<_>>();
+        let mut h = Vec::with_capacity(n);
+        let mut c = Vec::with_capacity(n);
+        for j in 0..n {
+            let i = sigmoid(&gates[j]);
+            let f = sigmoid(&gates[n + j]);
+            let g = tanh(&gates[2 * n + j]);
+            let o = sigmoid(&gates[3 * n + j]);
+            let cj = add(&mul(&f, &state.c[j]), &mul(&i, &g));
+            h.push(mul(&o, &tanh(&cj)));
+            c.push(cj);
+        }
+        LstmState { h, c }
+    }
+
+    fn output(state: &LstmState) -> Vec<Unit> {
+        state.h.clone()
+    }
+
+    fn detach_state(state: &LstmState) -> LstmState {
+        LstmState {
+            h: detach_all(&state.h),
+            c: detach_all(&state.c),
+        }
+    }
+}
+
+/// As a `Module`, a cell takes one step: the input is the step input followed
+/// by the flattened state (`h`, then `c` for an LSTM), and the output is the
+/// new state in the same layout.
+macro_rules! cell_module {
+    ($cell:ty, $name:expr, $state_len:expr, $unpack:expr, $pack:expr) => {
+        impl Module for $cell {
+            fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+                let n = self.hidden_size();
+                let (x, state) = split_input($name, input, self.ih.in_features, $state_len(n));
+                $pack(self.step(x, &$unpack(state, n)))
+            }
+
+            fn in_features(&self) -> Option<usize> {
+                Some(self.ih.in_features + $state_len(self.hidden_size()))
+            }
+
+            fn out_features(&self, _input: usize) -> usize {
+                $state_len(self.hidden_size())
+            }
+
+            fn children(&self) -> Vec<(String, &dyn Module)> {
+                vec![("ih".to_string(), &self.ih), ("hh".to_string(), &self.hh)]
+            }
+
+            fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+                vec![&mut self.ih, &mut self.hh]
+            }
+        }
+    };
+}
+
+cell_module!(RnnCell, "RnnCell", |n| n, |s: &[Unit], _| s.to_vec(), |h| h);
+cell_module!(GruCell, "GruCell", |n| n, |s: &[Unit], _| s.to_vec(), |h| h);
+cell_module!(
+    LstmCell,
+    "LstmCell",
+    |n| 2 * n,
+    |s: &[Unit], n| LstmState {
+        h: s[..n].to_vec(),
+        c: s[n..].to_vec(),
+    },
+    |s: LstmState| [s.h, s.c].concat()
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::backward;
+    use crate::fundamental::unit::new_unit;
+    use crate::nn::init::reseed;
+    use crate::nn::loss::{mse, Reduction};
+
+    fn sequence(values: &[f32]) -> Vec<Vec<Unit>> {
+        values.iter().map(|&v| vec![new_unit(v)]).collect()
+    }
+
+    fn sum(xs: &[Unit]) -> Unit {
+        xs[1..].iter().fold(xs[0].clone(), |acc, x| add(&acc, x))
+    }
+
+    #[test]
+    fn test_shapes_and_parameters() {
+        let rnn = RnnCell::new(3, 4);
+        let gru = GruCell::new(3, 4);
+        let lstm = LstmCell::new(3, 4);
+        assert_eq!(rnn.num_parameters(), 3 * 4 + 4 + 4 * 4);
+        assert_eq!(gru.num_parameters(), 3 * (3 * 4 + 4 + 4 * 4 + 4));
+        assert_eq!(lstm.num_parameters(), 4 * (3 * 4 + 4 + 4 * 4));
+        assert_eq!(lstm.named_parameters()[0].0, "ih.weights.0.0");
+
+        let inputs = (0..5)
+            .map(|t| (0..3).map(|i| new_unit((t * i) as f32 * 0.1)).collect())
+            .collect::<Vec<Vec<Unit>>>();
+        let (outputs, state) = unroll(&lstm, &inputs, None, None);
+        assert_eq!(outputs.len(), 5);
+        assert!(outputs.iter().all(|h| h.len() == 4));
+        assert_eq!(state.c.len(), 4);
+
+        // a single `forward` step agrees with `step`
+        let (_, h) = unroll(&gru, &inputs[..1], None, None);
+        let step = gru.forward(&[inputs[0].clone(), zeros(4)].concat());
+        assert!(h.iter().zip(step.iter()).all(|(a, b)| a.data() == b.data()));
+        assert_eq!(lstm.forward(&zeros(3 + 8)).len(), 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "LstmCell expects 2 inputs followed by 6 state values, got 5 values")]
+    fn test_forward_size_mismatch() {
+        LstmCell::new(2, 3).forward(&zeros(5));
+    }
+
+    #[test]
+    fn test_truncated_bptt_limits_gradient_reach() {
+        let cell = RnnCell::new(1, 3);
+        let inputs = sequence(&[1.0, -1.0, 0.5, 2.0, -0.5, 1.0]);
+
+        let (outputs, _) = unroll(&cell, &inputs, None, None);
+        backward(&sum(outputs.last().unwrap()));
+        assert!(inputs.iter().all(|x| x[0].grad() != 0.0));
+
+        let inputs = sequence(&[1.0, -1.0, 0.5, 2.0, -0.5, 1.0]);
+        let (outputs, _) = unroll(&cell, &inputs, None, Some(2));
+        backward(&sum(outputs.last().unwrap()));
+        // the last segment is steps 4 and 5
+        assert!(inputs[..4].iter().all(|x| x[0].grad() == 0.0));
+        assert!(inputs[4..].iter().all(|x| x[0].grad() != 0.0));
+    }
+
+    /// Echo the previous input: the target at step t is the input at t - 1,
+    /// which the cell can only produce by remembering it.
+    fn train_echo<C: RecurrentCell>(mut cell: C) {
+        let readout = Linear::new(cell.hidden_size(), 1, true);
+        reseed(&cell, 0.5, 5);
+        reseed(&readout, 0.5, 6);
+        let values = [0.5, -0.3, 0.8, -0.6, 0.1, 0.7, -0.9, 0.4, 0.2, -0.5];
+        let loss = |cell: &C| {
+            let (outputs, _) = unroll(cell, &sequence(&values), None, Some(4));
+            let predictions = outputs[1..]
+                .iter()
+                .map(|h| readout.eval(h).remove(0))
+                .collect::<Vec<_>>();
+            let targets = values[..values.len() - 1]
+                .iter()
+                .map(|&v| Unit::from(v))
+                .collect::<Vec<_>>();
+            mse(&predictions, &targets, Reduction::Mean).remove(0)
+        };
+
+        let first = loss(&cell).data();
+        for _ in 0..300 {
+            cell.zero_grad();
+            readout.parameters().iter().for_each(|p| p.zero_grad());
+            backward(&loss(&cell));
+            for p in cell.parameters().iter().chain(readout.parameters().iter()) {
+                p.adjust(-0.2);
+            }
+        }
+        let last = loss(&cell).data();
+        assert!(last < first * 0.5, "loss went from {} to {}", first, last);
+    }
+
+    #[test]
+    fn test_cells_learn_to_echo() {
+        train_echo(RnnCell::new(1, 6));
+        train_echo(GruCell::new(1, 6));
+        train_echo(LstmCell::new(1, 6));
+    }
+}
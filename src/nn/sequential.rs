@@ -0,0 +1,247 @@
+use super::*;
+use crate::nn::activation::Activation;
+use crate::nn::combinator::Residual;
+use crate::nn::dropout::Dropout;
+use crate::nn::linear::Linear;
+use crate::nn::mlp::MLP;
+
+/// Runs its modules one after another, feeding each one's output to the next.
+///
+/// Built fluently, e.g.
+/// `Sequential::new().linear(3, 16).relu().dropout(0.1).linear(16, 1).tanh()`.
+/// Every module added is checked against the output size of the one before
+/// it, so a mismatch panics while the model is built rather than during
+/// training.
+#[derive(Default)]
+pub struct Sequential {
+    pub modules: Vec<Box<dyn Module>>,
+    in_features: Option<usize>,
+    width: Option<usize>,
+}
+
+impl Sequential {
+    pub fn new() -> Self {
+        Sequential::default()
+    }
+
+    /// Appends any module, checking that it accepts the current output size.
+    pub fn then<M: Module + 'static>(self, module: M) -> Self {
+        self.then_boxed(Box::new(module))
+    }
+
+    /// The width is unknown after a module that takes any number of inputs
+    /// comes first, e.g. an `Embedding`; the next check is then skipped and
+    /// the container's own `in_features` stays `None`.
+    pub fn then_boxed(mut self, module: Box<dyn Module>) -> Self {
+        if self.modules.is_empty() {
+            self.in_features = module.in_features();
+            self.width = self.in_features;
+        } else if let Some(required) = module.in_features() {
+            match self.width {
+                Some(width) => assert_eq!(
+                    width,
+                    required,
+                    "module {} of Sequential takes {} inputs, but the previous module produces {}",
+                    self.modules.len(),
+                    required,
+                    width
+                ),
+                None => self.width = Some(required),
+            }
+        }
+        self.width = self.width.map(|w| module.out_features(w));
+        self.modules.push(module);
+        self
+    }
+
+    pub fn linear(self, in_features: usize, out_features: usize) -> Self {
+        self.then(Linear::new(in_features, out_features, true))
+    }
+
+    pub fn activation(self, activation: Activation) -> Self {
+        self.then(activation)
+    }
+
+    pub fn relu(self) -> Self {
+        self.activation(Activation::ReLU)
+    }
+
+    pub fn leaky_relu(self, alpha: f32) -> Self {
+        self.activation(Activation::LeakyReLU(alpha))
+    }
+
+    pub fn tanh(self) -> Self {
+        self.activation(Activation::Tanh)
+    }
+
+    pub fn sigmoid(self) -> Self {
+        self.activation(Activation::Sigmoid)
+    }
+
+    pub fn gelu(self) -> Self {
+        self.activation(Activation::GELU)
+    }
+
+    pub fn silu(self) -> Self {
+        self.activation(Activation::SiLU)
+    }
+
+    pub fn elu(self, alpha: f32) -> Self {
+        self.activation(Activation::ELU(alpha))
+    }
+
+    pub fn dropout(self, p: f32) -> Self {
+        self.then(Dropout::new(p))
+    }
+
+    /// Dropout drawing its masks from `seed`, for reproducible runs.
+    pub fn dropout_seeded(self, p: f32, seed: u64) -> Self {
+        self.then(Dropout::with_seed(p, seed))
+    }
+
+    /// Appends `module` wrapped in a skip connection, `x + module(x)`.
+    pub fn residual<M: Module + 'static>(self, module: M) -> Self {
+        self.then(Residual::new(module))
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+}
+
+impl Module for Sequential {
+    fn forward(&self, input: &[Unit]) -> Vec<Unit> {
+        let mut output = input.to_vec();
+        for m in self.modules.iter() {
+            output = m.forward(&output);
+        }
+        output
+    }
+
+    fn forward_batch(&self, batch: &[Vec<Unit>]) -> Vec<Vec<Unit>> {
+        let mut output = batch.to_vec();
+        for m in self.modules.iter() {
+            output = m.forward_batch(&output);
+        }
+        output
+    }
+
+    fn in_features(&self) -> Option<usize> {
+        self.in_features
+    }
+
+    fn out_features(&self, input: usize) -> usize {
+        self.modules.iter().fold(input, |n, m| m.out_features(n))
+    }
+
+    fn children(&self) -> Vec<(String, &dyn Module)> {
+        self.modules
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (i.to_string(), m.as_ref()))
+            .collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn Module> {
+        self.modules
+            .iter_mut()
+            .map(|m| m.as_mut() as &mut dyn Module)
+            .collect()
+    }
+}
+
+/// The layers of an `MLP`, in order. The activations stay inside the layers.
+impl From<MLP> for Sequential {
+    fn from(mlp: MLP) -> Self {
+        mlp.layers
+            .into_iter()
+            .fold(Sequential::new(), |seq, layer| seq.then(layer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::unit::new_unit;
+    use crate::nn::embedding::Embedding;
+
+    #[test]
+    fn test_builder_tracks_sizes() {
+        let seq = Sequential::new().linear(3, 16).relu().linear(16, 1).tanh();
+        assert_eq!(seq.len(), 4);
+        assert_eq!(seq.in_features(), Some(3));
+        assert_eq!(seq.out_features(3), 1);
+        assert_eq!(seq.num_parameters(), 3 * 16 + 16 + 16 + 1);
+
+        let y = seq.forward(&[new_unit(0.5), new_unit(-1.0), new_unit(2.0)]);
+        assert_eq!(y.len(), 1);
+        assert!(y[0].data().abs() <= 1.0);
+
+        let names = seq.named_parameters();
+        assert_eq!(names[0].0, "0.weights.0.0");
+        assert_eq!(names.last().unwrap().0, "2.bias.0");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "module 2 of Sequential takes 8 inputs, but the previous module produces 16"
+    )]
+    fn test_builder_rejects_mismatch() {
+        Sequential::new().linear(3, 16).relu().linear(8, 1);
+    }
+
+    #[test]
+    fn test_unknown_width_after_embedding() {
+        let seq = Sequential::new()
+            .then(Embedding::new(10, 4))
+            .then(Linear::new(3, 2, true));
+        assert_eq!(seq.in_features(), None);
+        assert_eq!(seq.out_features(1), 2);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "module 2 of Sequential takes 3 inputs, but the previous module produces 2"
+    )]
+    fn test_checks_resume_after_unknown_width() {
+        Sequential::new()
+            .then(Embedding::new(10, 4))
+            .linear(3, 2)
+            .linear(3, 1);
+    }
+
+    #[test]
+    fn test_seeded_dropout_is_reproducible() {
+        let x = (0..32).map(|_| new_unit(1.0)).collect::<Vec<_>>();
+        let run = || {
+            Sequential::new()
+                .relu()
+                .dropout_seeded(0.5, 11)
+                .forward(&x)
+                .iter()
+                .map(|y| y.data())
+                .collect::<Vec<_>>()
+        };
+        let first = run();
+        assert_eq!(first, run());
+        assert!(first.contains(&0.0) && first.contains(&2.0));
+    }
+
+    #[test]
+    fn test_from_mlp() {
+        let mlp = MLP::new(2, vec![3, 4], 1);
+        let x = vec![new_unit(1.0), new_unit(-2.0)];
+        let expected = mlp.eval(x.clone())[0].data();
+        let params = mlp.num_parameters();
+
+        let seq = Sequential::from(mlp);
+        assert_eq!(seq.len(), 3);
+        assert_eq!(seq.num_parameters(), params);
+        assert_eq!(seq.in_features(), Some(2));
+        assert_eq!(seq.forward(&x)[0].data(), expected);
+    }
+}
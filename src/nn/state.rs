@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::nn::Module;
+
+/// Parameter values keyed by their dotted name, as produced by
+/// `Module::state_dict`.
+pub type StateDict = BTreeMap<String, f32>;
+
+/// Keys that did not line up between a model and a state dict.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IncompatibleKeys {
+    /// parameters of the model with no value in the state dict
+    pub missing: Vec<String>,
+    /// entries of the state dict that name no parameter of the model
+    pub unexpected: Vec<String>,
+}
+
+impl IncompatibleKeys {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+impl fmt::Display for IncompatibleKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "state dict does not match the model: missing keys {:?}, unexpected keys {:?}",
+            self.missing, self.unexpected
+        )
+    }
+}
+
+impl Error for IncompatibleKeys {}
+
+pub(crate) fn collect<M: Module + ?Sized>(module: &M) -> StateDict {
+    let mut state = module
+        .named_parameters()
+        .into_iter()
+        .map(|(n, p)| (n, p.data()))
+        .collect::<StateDict>();
+    state.extend(module.named_buffers());
+    state
+}
+
+/// Copies the values in `state` into the parameters and buffers of `module`.
+/// In strict mode nothing is changed unless the keys match exactly.
+pub(crate) fn load<M: Module + ?Sized>(
+    module: &M,
+    state: &StateDict,
+    strict: bool,
+) -> Result<IncompatibleKeys, IncompatibleKeys> {
+    let params = module.named_parameters();
+    let buffers = module
+        .named_buffers()
+        .into_iter()
+        .map(|(n, _)| n)
+        .collect::<Vec<_>>();
+    let names = params.iter().map(|(n, _)| n).chain(buffers.iter());
+    let mut keys = IncompatibleKeys::default();
+    for name in names.clone() {
+        if !state.contains_key(name) {
+            keys.missing.push(name.clone());
+        }
+    }
+    let known = names.map(|n| n.as_str()).collect::<HashSet<_>>();
+    for name in state.keys() {
+        if !known.contains(name.as_str()) {
+            keys.unexpected.push(name.clone());
+        }
+    }
+    if strict && !keys.is_empty() {
+        return Err(keys);
+    }
+    for (name, p) in params.iter() {
+        if let Some(&value) = state.get(name) {
+            p.borrow_mut().data = value;
+        }
+    }
+    for name in buffers.iter() {
+        if let Some(&value) = state.get(name) {
+            module.set_buffer(name, value);
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::nn::mlp::MLP;
+    use crate::nn::Module;
+
+    #[test]
+    fn test_round_trip_between_models() {
+        let a = MLP::new(3, vec![4, 2], 1);
+        let mut b = MLP::new(3, vec![4, 2], 1);
+        let state = a.state_dict();
+        assert_eq!(state.len(), a.num_parameters());
+        assert_eq!(
+            state["layers.1.neurons.1.bias"],
+            a.layers[1].neurons[1].bias.data()
+        );
+
+        let keys = b.load_state_dict(&state, true).unwrap();
+        assert!(keys.is_empty());
+        assert_eq!(b.state_dict(), state);
+    }
+
+    #[test]
+    fn test_strict_and_relaxed_loading() {
+        let mut small = MLP::new(2, vec![2], 1);
+        let before = small.state_dict();
+        let mut state = MLP::new(2, vec![3], 1).state_dict();
+        state.insert("head.bias".to_string(), 1.0);
+
+        let err = small.load_state_dict(&state, true).unwrap_err();
+        assert_eq!(err.missing, Vec::<String>::new());
+        assert!(err.unexpected.contains(&"head.bias".to_string()));
+        assert!(err
+            .unexpected
+            .contains(&"layers.0.neurons.2.bias".to_string()));
+        assert!(err.to_string().contains("unexpected keys"));
+        // nothing was copied
+        assert_eq!(small.state_dict(), before);
+
+        let keys = small.load_state_dict(&state, false).unwrap();
+        assert_eq!(keys, err);
+        assert_eq!(
+            small.layers[0].neurons[1].bias.data(),
+            state["layers.0.neurons.1.bias"]
+        );
+
+        state.remove("layers.1.neurons.0.bias");
+        let err = small.load_state_dict(&state, true).unwrap_err();
+        assert_eq!(err.missing, vec!["layers.1.neurons.0.bias".to_string()]);
+    }
+}
@@ -1 +1,3 @@
 pub mod sgd;
+
+pub use sgd::SGD;
@@ -1 +1,111 @@
+use crate::fundamental::Unit;
 
+/// Stochastic gradient descent, optionally with momentum:
+///
+/// ```text
+/// v = momentum * v + grad
+/// p = p - lr * v
+/// ```
+///
+/// Frozen parameters (`requires_grad == false`) are skipped, so a module can
+/// be frozen or unfrozen after the optimizer is built.
+#[derive(Debug)]
+pub struct SGD {
+    pub params: Vec<Unit>,
+    pub lr: f32,
+    pub momentum: f32,
+    velocity: Vec<f32>,
+}
+
+impl SGD {
+    pub fn new(params: Vec<Unit>, lr: f32) -> Self {
+        let velocity = vec![0.0; params.len()];
+        SGD {
+            params,
+            lr,
+            momentum: 0.0,
+            velocity,
+        }
+    }
+
+    pub fn with_momentum(mut self, momentum: f32) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn step(&mut self) {
+        for (p, v) in self.params.iter().zip(self.velocity.iter_mut()) {
+            let mut u = p.borrow_mut();
+            if !u.requires_grad {
+                continue;
+            }
+            *v = self.momentum * *v + u.grad;
+            u.data -= self.lr * *v;
+        }
+    }
+
+    pub fn zero_grad(&self) {
+        self.params.iter().for_each(|p| p.zero_grad());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamental::op::{backward, mul};
+    use crate::fundamental::unit::new_unit;
+    use crate::nn::activation::Activation;
+    use crate::nn::loss::{mse, Reduction};
+    use crate::nn::mlp::MLP;
+    use crate::nn::Module;
+
+    #[test]
+    fn test_momentum_step() {
+        let w = new_unit(1.0);
+        let mut opt = SGD::new(vec![w.clone()], 0.1).with_momentum(0.5);
+        for _ in 0..2 {
+            opt.zero_grad();
+            // d(2w)/dw = 2
+            backward(&mul(&w, &Unit::from(2.0)));
+            opt.step();
+        }
+        // v = 2, then 0.5 * 2 + 2 = 3
+        assert!((w.data() - (1.0 - 0.2 - 0.3)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_frozen_layer_is_not_updated() {
+        let mlp =
+            MLP::with_activations(2, vec![4, 1], vec![Activation::Tanh, Activation::Identity]);
+        mlp.layers[0].freeze();
+        assert_eq!(mlp.trainable_parameters().len(), 4 + 1);
+
+        let frozen = mlp.layers[0]
+            .parameters()
+            .iter()
+            .map(|p| p.data())
+            .collect::<Vec<_>>();
+        let trained = mlp.layers[1].parameters()[0].data();
+        let mut opt = SGD::new(mlp.parameters(), 0.1);
+        let x = vec![new_unit(0.5), new_unit(-1.0)];
+        x.iter().for_each(|u| u.set_requires_grad(false));
+        for _ in 0..3 {
+            opt.zero_grad();
+            let loss = mse(&mlp.eval(x.clone()), &[Unit::from(3.0)], Reduction::Mean);
+            backward(&loss[0]);
+            opt.step();
+        }
+        let after = mlp.layers[0]
+            .parameters()
+            .iter()
+            .map(|p| p.data())
+            .collect::<Vec<_>>();
+        assert_eq!(frozen, after);
+        assert!(mlp.layers[0].parameters().iter().all(|p| p.grad() == 0.0));
+        assert!(x.iter().all(|u| u.grad() == 0.0));
+        assert_ne!(mlp.layers[1].parameters()[0].data(), trained);
+
+        mlp.layers[0].unfreeze();
+        assert_eq!(mlp.trainable_parameters().len(), mlp.num_parameters());
+    }
+}